@@ -592,6 +592,8 @@ impl TryFrom<Value> for ExternalDocumentId {
 pub enum MatchingStrategy {
     /// Remove query words from last to first
     Last,
+    /// Remove query words from first to last
+    First,
     /// All query words are mandatory
     All,
     /// Remove query words from the most frequent to the least
@@ -608,6 +610,7 @@ impl From<MatchingStrategy> for TermsMatchingStrategy {
     fn from(other: MatchingStrategy) -> Self {
         match other {
             MatchingStrategy::Last => Self::Last,
+            MatchingStrategy::First => Self::First,
             MatchingStrategy::All => Self::All,
             MatchingStrategy::Frequency => Self::Frequency,
         }
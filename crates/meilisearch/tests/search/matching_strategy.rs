@@ -58,6 +58,13 @@ async fn simple_search() {
         })
         .await;
 
+    index
+        .search(json!({"q": "Captain Marvel", "matchingStrategy": "first", "attributesToRetrieve": ["id"]}), |response, code| {
+            snapshot!(code, @"200 OK");
+            snapshot!(response["hits"], @r###"[{"id":"3"},{"id":"4"},{"id":"5"}]"###);
+        })
+        .await;
+
     index
         .search(json!({"q": "Captain Marvel", "matchingStrategy": "all", "attributesToRetrieve": ["id"]}), |response, code| {
             snapshot!(code, @"200 OK");
@@ -74,6 +74,22 @@ pub enum InternalError {
     AbortedIndexation,
     #[error("The matching words list contains at least one invalid member")]
     InvalidMatchingWords,
+    #[error("The geo faceted document {docid} doesn't contain any lat or lng")]
+    GeoFacetedDocumentWithoutCoordinates { docid: DocumentId },
+    #[error("The ranking rule {rule_id} returned candidates that are not part of its universe")]
+    RankingRuleCandidatesOutsideUniverse { rule_id: String },
+    #[error("The ranking rule {rule_id} returned more non-empty buckets than the {max_buckets} documents of its universe")]
+    RankingRuleTooManyBuckets { rule_id: String, max_buckets: u64 },
+    #[error("The ranking rule {rule_id} returned the document {docid} that was already returned")]
+    RankingRuleDuplicateCandidates { rule_id: String, docid: DocumentId },
+    #[error("The filtering ranking rule {rule_id} can't come after the sorting ranking rule {sort_rule_id}")]
+    FilteringRankingRuleAfterSort { rule_id: String, sort_rule_id: String },
+    #[error(
+        "The geo sort must use the rtree but the index has geo faceted documents and no rtree"
+    )]
+    MissingGeoRtree,
+    #[error("The geo fields of the index are inconsistent, {candidates} geo faceted documents are affected: {reason}")]
+    InconsistentGeoFields { candidates: u64, reason: &'static str },
     #[error(transparent)]
     ArroyError(#[from] arroy::Error),
     #[error(transparent)]
@@ -187,6 +203,12 @@ and can not be more than 511 bytes.", .document_id.to_string()
     InvalidLmdbOpenOptions,
     #[error("You must specify where `sort` is listed in the rankingRules setting to use the sort parameter at search time.")]
     SortRankingRuleMissing,
+    #[error("The search query is too complex: it makes a query graph of {nodes} nodes, but it is limited to {max_nodes}.")]
+    QueryTooComplex { nodes: usize, max_nodes: usize },
+    #[error("Invalid geo sort: {reason}.")]
+    InvalidGeoSort { reason: String },
+    #[error("The exact boost of the words ranking rule must be between `0.0`, included, and `1.0`, excluded, without being too close to `1.0` for the number of query words, but found `{exact_boost}`.")]
+    InvalidExactBoost { exact_boost: f64 },
     #[error("The database file is in an invalid state.")]
     InvalidStoreFile,
     #[error("Maximum database size has been reached.")]
@@ -511,6 +511,12 @@ impl Index {
         }
     }
 
+    /// Returns the serialized `rtree` which associates coordinates to documents ids, see [`Self::geo_rtree`].
+    #[cfg(feature = "parallel-prefetch")]
+    pub(crate) fn geo_rtree_bytes<'t>(&self, rtxn: &'t RoTxn<'_>) -> Result<Option<&'t [u8]>> {
+        Ok(self.main.remap_types::<Str, Bytes>().get(rtxn, main_key::GEO_RTREE_KEY)?)
+    }
+
     /* geo faceted */
 
     /// Writes the documents ids that are faceted with a _geo field.
@@ -545,6 +551,53 @@ impl Index {
             None => Ok(RoaringBitmap::new()),
         }
     }
+
+    /// Checks that the documents faceted with a _geo field, the geo rtree, and the fields ids map agree.
+    ///
+    /// The rtree may be missing as long as it wasn't built, but when it exists it must contain exactly
+    /// the geo faceted documents.
+    pub fn validate_geo_consistency(&self, rtxn: &RoTxn<'_>) -> Result<()> {
+        let geo_faceted_docids = self.geo_faceted_documents_ids(rtxn)?;
+        if geo_faceted_docids.is_empty() {
+            return Ok(());
+        }
+
+        let fid_map = self.fields_ids_map(rtxn)?;
+        for (field, reason) in
+            [("_geo.lat", "no field id for `_geo.lat`"), ("_geo.lng", "no field id for `_geo.lng`")]
+        {
+            if fid_map.id(field).is_none() {
+                return Err(InternalError::InconsistentGeoFields {
+                    candidates: geo_faceted_docids.len(),
+                    reason,
+                }
+                .into());
+            }
+        }
+
+        if let Some(rtree) = self.geo_rtree(rtxn)? {
+            let rtree_docids: RoaringBitmap = rtree.iter().map(|point| point.data.0).collect();
+            let missing = &geo_faceted_docids - &rtree_docids;
+            if !missing.is_empty() {
+                return Err(InternalError::InconsistentGeoFields {
+                    candidates: missing.len(),
+                    reason: "documents are missing from the rtree",
+                }
+                .into());
+            }
+            let unknown = rtree_docids - &geo_faceted_docids;
+            if !unknown.is_empty() {
+                return Err(InternalError::InconsistentGeoFields {
+                    candidates: unknown.len(),
+                    reason: "the rtree contains documents that are not geo faceted",
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
     /* field distribution */
 
     /// Writes the field distribution which associates every field name with
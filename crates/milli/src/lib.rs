@@ -44,8 +44,9 @@ pub use filter_parser::{Condition, FilterCondition, Span, Token};
 use fxhash::{FxHasher32, FxHasher64};
 pub use grenad::CompressionType;
 pub use search::new::{
-    execute_search, filtered_universe, DefaultSearchLogger, GeoSortStrategy, SearchContext,
-    SearchLogger, VisualSearchLogger,
+    execute_search, filtered_universe, DefaultSearchLogger, GeoDistanceUnit, GeoSortStrategy,
+    JsonSearchEvent, JsonSearchLogger, MetricsLogger, ProgressLogger, SearchContext, SearchLogger,
+    SearchMetrics, VisualSearchLogger,
 };
 use serde_json::Value;
 pub use thread_pool_no_abort::{PanicCatched, ThreadPoolNoAbort, ThreadPoolNoAbortBuilder};
@@ -160,6 +161,13 @@ impl TimeBudget {
         Self::new(std::time::Duration::from_secs(u64::MAX))
     }
 
+    /// Creates a time budget that is exceeded once the given deadline is reached.
+    ///
+    /// A deadline that is already in the past gives an empty budget.
+    pub fn until(deadline: std::time::Instant) -> Self {
+        Self::new(deadline.saturating_duration_since(std::time::Instant::now()))
+    }
+
     #[cfg(test)]
     pub fn with_stop_after(mut self, stop_after: usize) -> Self {
         use std::sync::atomic::AtomicUsize;
@@ -329,6 +337,54 @@ pub fn lat_lng_to_xyz(coord: &[f64; 2]) -> [f64; 3] {
     [x, y, z]
 }
 
+/// Return the antipode of a point, the point on the opposite side of the earth, as latitude and longitude.
+///
+/// The returned longitude is always in `(-180, 180]`. The farthest points from a point are the nearest
+/// ones from its antipode, which is how the descending geo sort walks the rtree.
+///
+/// ```
+/// use milli::antipode;
+///
+/// assert_eq!(antipode([0., 0.]), [-0., 180.]);
+/// assert_eq!(antipode([90., 0.]), [-90., 180.]);
+/// assert_eq!(antipode([45., 90.]), [-45., -90.]);
+/// ```
+pub fn antipode(mut coord: [f64; 2]) -> [f64; 2] {
+    coord[0] *= -1.;
+    // in the case of x,0 we want to return -x,180
+    let lng = (coord[1] + 180.).rem_euclid(360.);
+    coord[1] = if lng > 180. { lng - 360. } else { lng };
+
+    coord
+}
+
+/// Return the initial bearing of the great circle going from the point `a` to the point `b`, in degrees
+/// clockwise from the north, in `[0, 360)`. Each points are composed of a latitude and a longitude.
+///
+/// Returns `None` when both points are the same, as the bearing is undefined.
+///
+/// ```
+/// use milli::great_circle_bearing;
+///
+/// assert_eq!(great_circle_bearing(&[0., 0.], &[1., 0.]), Some(0.));
+/// assert_eq!(great_circle_bearing(&[0., 0.], &[-1., 0.]).map(f64::round), Some(180.));
+/// assert_eq!(great_circle_bearing(&[0., 0.], &[0., 0.]), None);
+/// ```
+pub fn great_circle_bearing(a: &[f64; 2], b: &[f64; 2]) -> Option<f64> {
+    if a == b {
+        return None;
+    }
+    let [lat_a, lng_a] = a.map(f64::to_radians);
+    let [lat_b, lng_b] = b.map(f64::to_radians);
+    let delta_lng = lng_b - lng_a;
+    let y = delta_lng.sin() * lat_b.cos();
+    let x = lat_a.cos() * lat_b.sin() - lat_a.sin() * lat_b.cos() * delta_lng.cos();
+    // `atan2` is in `(-180, 180]`, a bearing slightly west of the north must come after all the others
+    let bearing = y.atan2(x).to_degrees().rem_euclid(360.);
+    // `rem_euclid` rounds the tiny negative angles up to `360`
+    Some(if bearing >= 360. { 0. } else { bearing })
+}
+
 /// Returns `true` if the field match one of the faceted fields.
 /// See the function [`is_faceted_by`] below to see what “matching” means.
 pub fn is_faceted(field: &str, faceted_fields: impl IntoIterator<Item = impl AsRef<str>>) -> bool {
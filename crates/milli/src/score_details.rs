@@ -4,10 +4,13 @@ use itertools::Itertools;
 use serde::Serialize;
 
 use crate::distance_between_two_points;
+use crate::error::UserError;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ScoreDetails {
     Words(Words),
+    /// Returned by the words ranking rule given an exact boost, see [`BoostedWords`]
+    BoostedWords(BoostedWords),
     Typo(Typo),
     Proximity(Rank),
     Fid(Rank),
@@ -37,6 +40,35 @@ enum RankOrValue<'a> {
 }
 
 impl ScoreDetails {
+    /// The name of the score, as reported when explaining the score of a document.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScoreDetails::Words(_) => "words",
+            ScoreDetails::BoostedWords(_) => "words",
+            ScoreDetails::Typo(_) => "typo",
+            ScoreDetails::Proximity(_) => "proximity",
+            ScoreDetails::Fid(_) => "fid",
+            ScoreDetails::Position(_) => "position",
+            ScoreDetails::ExactAttribute(_) => "exact_attribute",
+            ScoreDetails::ExactWords(_) => "exact_words",
+            ScoreDetails::Sort(_) => "sort",
+            ScoreDetails::Vector(_) => "vector_sort",
+            ScoreDetails::GeoSort(_) => "geo_sort",
+            ScoreDetails::Skipped => "skipped",
+        }
+    }
+
+    /// The score between `0.0` and `1.0` given by this ranking rule alone.
+    ///
+    /// Returns `None` for the ranking rules that order the documents without scoring them, like the sort.
+    pub fn explained_score(&self) -> Option<f64> {
+        match self.rank_or_value() {
+            RankOrValue::Rank(rank) => Some(rank.local_score()),
+            RankOrValue::Score(score) => Some(score),
+            RankOrValue::Sort(_) | RankOrValue::GeoSort(_) => None,
+        }
+    }
+
     pub fn local_score(&self) -> Option<f64> {
         self.rank().map(Rank::local_score)
     }
@@ -44,6 +76,7 @@ impl ScoreDetails {
     pub fn rank(&self) -> Option<Rank> {
         match self {
             ScoreDetails::Words(details) => Some(details.rank()),
+            ScoreDetails::BoostedWords(details) => Some(details.rank()),
             ScoreDetails::Typo(details) => Some(details.rank()),
             ScoreDetails::Proximity(details) => Some(*details),
             ScoreDetails::Fid(details) => Some(*details),
@@ -68,6 +101,36 @@ impl ScoreDetails {
             .unwrap_or(1.0f64)
     }
 
+    /// Compute the score of a document by weighting the local scores of its ranking rules.
+    ///
+    /// `weights` is parallel to `details`, a weight of `0.0` makes the ranking rule not participate to the score.
+    /// The ranking rules without a score, like the sort, are ignored.
+    ///
+    /// The returned score is normalized between `0.0` and `1.0`.
+    pub fn weighted_global_score<'a>(
+        details: impl Iterator<Item = &'a Self> + 'a,
+        weights: &[f64],
+    ) -> f64 {
+        let mut total_weight = 0.0;
+        let mut weighted_score = 0.0;
+        for (details, weight) in details.zip(weights) {
+            debug_assert!(*weight >= 0.0, "ranking rule weights must be positive");
+            let local_score = match details.rank_or_value() {
+                RankOrValue::Rank(rank) => rank.local_score(),
+                RankOrValue::Score(score) => score,
+                RankOrValue::Sort(_) | RankOrValue::GeoSort(_) => continue,
+            };
+            total_weight += weight;
+            weighted_score += weight * local_score;
+        }
+
+        if total_weight == 0.0 {
+            1.0
+        } else {
+            weighted_score / total_weight
+        }
+    }
+
     pub fn score_values<'a>(
         details: impl Iterator<Item = &'a Self> + 'a,
     ) -> impl Iterator<Item = ScoreValue<'a>> + 'a {
@@ -90,6 +153,7 @@ impl ScoreDetails {
     fn rank_or_value(&self) -> RankOrValue<'_> {
         match self {
             ScoreDetails::Words(w) => RankOrValue::Rank(w.rank()),
+            ScoreDetails::BoostedWords(w) => RankOrValue::Rank(w.rank()),
             ScoreDetails::Typo(t) => RankOrValue::Rank(t.rank()),
             ScoreDetails::Proximity(p) => RankOrValue::Rank(*p),
             ScoreDetails::Fid(f) => RankOrValue::Rank(*f),
@@ -127,6 +191,17 @@ impl ScoreDetails {
                     details_map.insert("words".into(), words_details);
                     order += 1;
                 }
+                ScoreDetails::BoostedWords(boosted) => {
+                    let words_details = serde_json::json!({
+                            "order": order,
+                            "matchingWords": boosted.words.matching_words,
+                            "maxMatchingWords": boosted.words.max_matching_words,
+                            "exactBonus": boosted.exact_bonus,
+                            "score": boosted.rank().local_score(),
+                    });
+                    details_map.insert("words".into(), words_details);
+                    order += 1;
+                }
                 ScoreDetails::Typo(typo) => {
                     let typo_details = serde_json::json!({
                         "order": order,
@@ -303,6 +378,59 @@ impl Words {
     }
 }
 
+/// The score of the words ranking rule with a bonus for the documents matching all the query words.
+///
+/// The documents matching all the query words are given `exact_bonus` more ranks than the other ones, out of
+/// `exact_bonus` more ranks as well, such that their score is widened from the score of the next documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BoostedWords {
+    pub words: Words,
+    pub exact_bonus: u32,
+}
+
+impl BoostedWords {
+    /// Give the smallest exact bonus ensuring that the documents matching all the query words score at least
+    /// `exact_boost` above the ones of the first relaxation step, whose rank is one less.
+    ///
+    /// Returns [`UserError::InvalidExactBoost`] if the exact boost is not between `0.0`, included, and `1.0`,
+    /// excluded, or if it is so close to `1.0` that the ranks can't hold the bonus.
+    pub fn new(words: Words, exact_boost: f64) -> Result<Self, UserError> {
+        let invalid = || UserError::InvalidExactBoost { exact_boost };
+        if !(0.0..1.0).contains(&exact_boost) {
+            return Err(invalid());
+        }
+        // the maximum rank, `max_matching_words + exact_bonus`, must fit in a `u32`
+        let max_bonus = u32::MAX - words.max_matching_words;
+        // the gap between the scores of the two best ranks is `(bonus + 1) / (max_matching_words + bonus)`
+        let max_matching_words = words.max_matching_words as f64;
+        let min_bonus =
+            ((exact_boost * max_matching_words - 1.0) / (1.0 - exact_boost)).max(0.0).ceil();
+        if min_bonus > max_bonus as f64 {
+            return Err(invalid());
+        }
+        let mut exact_bonus = min_bonus as u32;
+        // the gap is computed like the scores, which the divisions may round up or down
+        let gap =
+            |bonus: u32| 1.0 - (max_matching_words - 1.0) / (max_matching_words + bonus as f64);
+        while exact_bonus > 0 && gap(exact_bonus - 1) >= exact_boost {
+            exact_bonus -= 1;
+        }
+        while gap(exact_bonus) < exact_boost {
+            exact_bonus =
+                exact_bonus.checked_add(1).filter(|&b| b <= max_bonus).ok_or_else(invalid)?;
+        }
+        Ok(Self { words, exact_bonus })
+    }
+
+    pub fn rank(&self) -> Rank {
+        let Words { matching_words, max_matching_words } = self.words;
+        // `new` ensures that the bonus fits, but the fields are public
+        let max_rank = max_matching_words.saturating_add(self.exact_bonus);
+        let rank = if matching_words == max_matching_words { max_rank } else { matching_words };
+        Rank { rank, max_rank }
+    }
+}
+
 /// Structure that is super similar to [`Words`], but whose semantics is a bit distinct.
 ///
 /// In exactness, the number of matching words can actually be 0 with a non-zero score,
@@ -493,3 +621,54 @@ impl GeoSort {
         self.value.map(|value| distance_between_two_points(&self.target_point, &value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_global_score() {
+        let details = [
+            ScoreDetails::Words(Words { matching_words: 1, max_matching_words: 2 }),
+            ScoreDetails::Sort(Sort {
+                field_name: "price".into(),
+                ascending: true,
+                redacted: false,
+                value: 2.into(),
+            }),
+            ScoreDetails::Typo(Typo { typo_count: 0, max_typo_count: 1 }),
+        ];
+
+        // the sort doesn't participate to the score
+        assert_eq!(ScoreDetails::weighted_global_score(details.iter(), &[1.0, 1.0, 1.0]), 0.75);
+        assert_eq!(ScoreDetails::weighted_global_score(details.iter(), &[3.0, 1.0, 1.0]), 0.625);
+        // a weight of zero only affects the order of the documents
+        assert_eq!(ScoreDetails::weighted_global_score(details.iter(), &[0.0, 1.0, 1.0]), 1.0);
+        assert_eq!(ScoreDetails::weighted_global_score(details.iter(), &[0.0, 0.0, 0.0]), 1.0);
+    }
+
+    #[test]
+    fn exact_boost_near_one() {
+        let words = Words { matching_words: 3, max_matching_words: 3 };
+        let relaxed_score = |boosted: BoostedWords| {
+            BoostedWords { words: Words { matching_words: 2, ..words }, ..boosted }
+                .rank()
+                .local_score()
+        };
+
+        // a boost close to `1.0` is given a large bonus, that still fits in the ranks
+        let exact_boost = 0.999_999;
+        let boosted = BoostedWords::new(words, exact_boost).unwrap();
+        assert_eq!(boosted.rank().local_score(), 1.0);
+        assert!(1.0 - relaxed_score(boosted) >= exact_boost);
+
+        // the bonus of the largest boost below `1.0` doesn't fit
+        let error = BoostedWords::new(words, 1.0 - f64::EPSILON).unwrap_err();
+        assert!(matches!(error, UserError::InvalidExactBoost { .. }), "unexpected error: {error}");
+
+        // nor does any bonus when the number of query words leaves no room for it
+        let words = Words { matching_words: u32::MAX - 1, max_matching_words: u32::MAX - 1 };
+        let error = BoostedWords::new(words, 0.5).unwrap_err();
+        assert!(matches!(error, UserError::InvalidExactBoost { .. }), "unexpected error: {error}");
+    }
+}
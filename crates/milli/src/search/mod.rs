@@ -46,6 +46,7 @@ pub struct Search<'a> {
     searchable_attributes: Option<&'a [String]>,
     geo_strategy: new::GeoSortStrategy,
     terms_matching_strategy: TermsMatchingStrategy,
+    exact_boost: Option<f64>,
     scoring_strategy: ScoringStrategy,
     words_limit: usize,
     exhaustive_number_hits: bool,
@@ -69,6 +70,7 @@ impl<'a> Search<'a> {
             searchable_attributes: None,
             geo_strategy: new::GeoSortStrategy::default(),
             terms_matching_strategy: TermsMatchingStrategy::default(),
+            exact_boost: None,
             scoring_strategy: Default::default(),
             exhaustive_number_hits: false,
             words_limit: 10,
@@ -127,6 +129,17 @@ impl<'a> Search<'a> {
         self
     }
 
+    /// Widens the score of the documents matching all the query words, such that it is at least
+    /// `exact_boost` above the score of the documents matching one word less.
+    ///
+    /// The order of the documents doesn't change. The exact boost is ignored when the `words` ranking rule
+    /// isn't used, otherwise the search fails with [`UserError::InvalidExactBoost`] if it isn't between `0.0`,
+    /// included, and `1.0`, excluded.
+    pub fn exact_boost(&mut self, exact_boost: f64) -> &mut Search<'a> {
+        self.exact_boost = Some(exact_boost);
+        self
+    }
+
     pub fn scoring_strategy(&mut self, value: ScoringStrategy) -> &mut Search<'a> {
         self.scoring_strategy = value;
         self
@@ -230,6 +243,7 @@ impl<'a> Search<'a> {
                 &mut ctx,
                 self.query.as_deref(),
                 self.terms_matching_strategy,
+                self.exact_boost,
                 self.scoring_strategy,
                 self.exhaustive_number_hits,
                 universe,
@@ -276,6 +290,7 @@ impl fmt::Debug for Search<'_> {
             searchable_attributes,
             geo_strategy: _,
             terms_matching_strategy,
+            exact_boost,
             scoring_strategy,
             words_limit,
             exhaustive_number_hits,
@@ -296,6 +311,7 @@ impl fmt::Debug for Search<'_> {
             .field("distinct", distinct)
             .field("searchable_attributes", searchable_attributes)
             .field("terms_matching_strategy", terms_matching_strategy)
+            .field("exact_boost", exact_boost)
             .field("scoring_strategy", scoring_strategy)
             .field("exhaustive_number_hits", exhaustive_number_hits)
             .field("words_limit", words_limit)
@@ -324,10 +340,14 @@ pub struct SearchResult {
 pub enum TermsMatchingStrategy {
     // remove last word first
     Last,
+    // remove first word first
+    First,
     // all words are mandatory
     All,
     // remove more frequent word first
     Frequency,
+    // remove last word first but keep at least the given number of words
+    MinMatch(usize),
 }
 
 impl Default for TermsMatchingStrategy {
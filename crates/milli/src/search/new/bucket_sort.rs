@@ -1,115 +1,668 @@
+use std::collections::{HashMap, VecDeque};
+
 use roaring::RoaringBitmap;
 
 use super::logger::SearchLogger;
-use super::ranking_rules::{BoxRankingRule, RankingRuleQueryTrait};
+use super::ranking_rules::{
+    BoxRankingRule, RankingRule, RankingRuleDetails, RankingRuleOutput, RankingRuleQueryTrait,
+};
 use super::SearchContext;
 use crate::score_details::{ScoreDetails, ScoringStrategy};
-use crate::search::new::distinct::{apply_distinct_rule, distinct_single_docid, DistinctOutput};
-use crate::{Result, TimeBudget};
+use crate::search::new::distinct::{
+    apply_distinct_rule, distinct_representative, distinct_single_docid, DistinctOutput,
+};
+use crate::{InternalError, Result, TimeBudget};
 
 pub struct BucketSortOutput {
     pub docids: Vec<u32>,
     pub scores: Vec<Vec<ScoreDetails>>,
+    /// The global score of each document, aligned with `docids`
+    ///
+    /// When weights are given to the bucket sort, it is the weighted average of the scores of the ranking rules.
+    /// When the scores are normalized, the first one is `1.0` and they strictly decrease with the rank of the documents.
+    pub blended_scores: Vec<f64>,
+    /// The additional details given by the ranking rules for each document, aligned with `docids`
+    ///
+    /// It is only computed when the bucket sort is asked for the details, and empty otherwise.
+    pub details: Vec<Vec<RankingRuleDetails>>,
+    /// The index in `docids` where each bucket of the first ranking rule starts
+    ///
+    /// The first document always starts a bucket, even when its bucket started on a previous page.
+    /// Without ranking rules, all the documents are in a single bucket.
+    pub bucket_boundaries: Vec<usize>,
+    /// The number of query words matched by each document, aligned with `docids`
+    ///
+    /// It is the number of words of the `words` ranking rule bucket in which the document was found,
+    /// or `0` if the `words` ranking rule wasn't used. Like the details it is computed from, it is empty
+    /// when the bucket sort isn't asked for the details.
+    pub matched_words: Vec<usize>,
+    /// The ids of the query words removed by the terms matching strategy for each document, aligned with `docids`
+    ///
+    /// See [`RankingRuleDetails::Words`] for how the ids map to the words of the query. It is empty
+    /// for a document when the `words` ranking rule wasn't used, and for all of them
+    /// when the bucket sort isn't asked for the details.
+    pub dropped_terms: Vec<Vec<u8>>,
+    /// The number of documents removed by the distinct rule in favor of each document, aligned with `docids`
+    ///
+    /// It is always `0` when the distinct rule is not applied.
+    pub distinct_counts: Vec<u64>,
+    /// The local score of each ranking rule for each document, keyed by the name of the score, aligned with `docids`
+    ///
+    /// It is only computed when the bucket sort is asked to explain the scores, and empty otherwise.
+    /// The ranking rules without a score, like the sort, don't appear in it.
+    pub explanations: Vec<HashMap<String, f64>>,
     pub all_candidates: RoaringBitmap,
+    /// Whether `all_candidates` was truncated to the maximum number of candidates given to the bucket sort
+    ///
+    /// The truncated candidates are the ones with the smallest ids, and may miss some of the returned documents.
+    /// The estimated total hits is bounded as well when it is the number of `all_candidates`.
+    pub candidates_truncated: bool,
+    /// An estimation of the total number of hits.
+    ///
+    /// It is the size of the initial universe when all the ranking rules preserve the count of documents,
+    /// and the number of `all_candidates` otherwise.
+    pub estimated_total_hits: u64,
 
     pub degraded: bool,
 }
 
-// TODO: would probably be good to regroup some of these inside of a struct?
+/// What the bucket sort loop returns besides the documents collected in a [`PageResults`].
+struct BucketSortSummary {
+    /// The index in the results where each bucket of the first ranking rule starts
+    bucket_boundaries: Vec<usize>,
+    all_candidates: RoaringBitmap,
+    candidates_truncated: bool,
+    estimated_total_hits: u64,
+    degraded: bool,
+}
+
+/// The documents collected for the current page.
+struct PageResults<'s> {
+    results: &'s mut CollectedResults,
+    /// The documents skipped before the offset or added to the page by this search,
+    /// only kept when the duplicates are checked, see [`BucketSortOptions::check_duplicates`]
+    seen: Option<RoaringBitmap>,
+}
+
+impl<'s> PageResults<'s> {
+    fn new(results: &'s mut CollectedResults, check_duplicates: bool) -> Self {
+        Self { results, seen: check_duplicates.then(RoaringBitmap::new) }
+    }
+
+    fn len(&self) -> usize {
+        self.results.docids.len()
+    }
+
+    /// The documents added to the page
+    fn returned(&self) -> &[u32] {
+        &self.results.docids
+    }
+
+    /// The first of the given documents that was already skipped or added to the page, if any,
+    /// always `None` when the duplicates are not checked
+    fn first_seen(&self, docids: &RoaringBitmap) -> Option<u32> {
+        (docids & self.seen.as_ref()?).min()
+    }
+
+    fn skip(&mut self, docids: impl IntoIterator<Item = u32>) {
+        if let Some(seen) = &mut self.seen {
+            seen.extend(docids);
+        }
+    }
+
+    fn extend(
+        &mut self,
+        docids: &[u32],
+        scores: &[ScoreDetails],
+        ranking_rule_details: &[Option<RankingRuleDetails>],
+        distinct_counts: &[u64],
+    ) {
+        if docids.is_empty() {
+            return;
+        }
+        if let Some(seen) = &mut self.seen {
+            seen.extend(docids.iter().copied());
+        }
+        let results = &mut *self.results;
+        results.docids.extend_from_slice(docids);
+        results.scores.extend(std::iter::repeat(scores.to_owned()).take(docids.len()));
+        if let Some(details) = &mut results.details {
+            let bucket_details: Vec<RankingRuleDetails> =
+                ranking_rule_details.iter().flatten().cloned().collect();
+            details.extend(std::iter::repeat(bucket_details).take(docids.len()));
+        }
+        results.distinct_counts.extend_from_slice(distinct_counts);
+    }
+}
+
+/// The results collected by [`bucket_sort`] in a [`BucketSortOutput`].
+#[derive(Default)]
+struct CollectedResults {
+    docids: Vec<u32>,
+    scores: Vec<Vec<ScoreDetails>>,
+    /// The details of each document, only collected when they are asked for
+    details: Option<Vec<Vec<RankingRuleDetails>>>,
+    distinct_counts: Vec<u64>,
+}
+
+impl CollectedResults {
+    fn new(details: bool) -> Self {
+        Self { details: details.then(Vec::new), ..Default::default() }
+    }
+
+    fn into_output(
+        self,
+        summary: BucketSortSummary,
+        weights: Option<&[f64]>,
+        normalize_scores: bool,
+        explain: bool,
+    ) -> BucketSortOutput {
+        let BucketSortSummary {
+            bucket_boundaries,
+            all_candidates,
+            candidates_truncated,
+            estimated_total_hits,
+            degraded,
+        } = summary;
+        debug_assert!(
+            self.docids.iter().all(|docid| all_candidates.contains(*docid)),
+            "the candidates must contain all the returned documents"
+        );
+        let details = self.details.unwrap_or_default();
+        BucketSortOutput {
+            blended_scores: blended_scores(&self.scores, weights, normalize_scores),
+            explanations: explanations(&self.scores, explain),
+            matched_words: matched_words(&details),
+            dropped_terms: dropped_terms(&details),
+            docids: self.docids,
+            scores: self.scores,
+            details,
+            bucket_boundaries,
+            distinct_counts: self.distinct_counts,
+            all_candidates,
+            candidates_truncated,
+            estimated_total_hits,
+            degraded,
+        }
+    }
+}
+
+/// Where a [`BucketSorter`] stopped after computing a page of results.
+enum SorterPosition {
+    /// The next page must be computed from scratch
+    Restart,
+    /// All the documents were returned, the next page is empty
+    Exhausted { all_candidates: RoaringBitmap, candidates_truncated: bool },
+    /// The bucket sort can resume from where it stopped
+    Resume(Box<BucketSortState>),
+}
+
+/// The state of the bucket sort loop, kept by a [`BucketSorter`] between its pages.
+///
+/// The ranking rules up to `cur_ranking_rule_index` are still iterating over their universe.
+struct BucketSortState {
+    cur_ranking_rule_index: usize,
+    ranking_rule_universes: Vec<RoaringBitmap>,
+    ranking_rule_scores: Vec<ScoreDetails>,
+    ranking_rule_details: Vec<Option<RankingRuleDetails>>,
+    all_candidates: RoaringBitmap,
+    candidates_truncated: bool,
+    leftover: Option<Leftover>,
+}
+
+/// The documents of a bucket that did not fit in the page, with the scores of the bucket.
+struct Leftover {
+    candidates: RoaringBitmap,
+    /// The distinct counts of the candidates, in the order of the candidates
+    distinct_counts: Option<Vec<u64>>,
+    ranking_rule_scores: Vec<ScoreDetails>,
+    ranking_rule_details: Vec<Option<RankingRuleDetails>>,
+}
+
+/// The options of a bucket sort, all of them disabled by default.
+///
+/// ```ignore
+/// let options = BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() };
+/// ```
+#[derive(Clone)]
+pub struct BucketSortOptions<'a> {
+    /// The fields of the distinct rule, the distinct attribute of the index when empty
+    pub distinct: &'a [&'a str],
+    /// The documents preferred as the representatives of their distinct group
+    pub is_preferred: Option<&'a dyn Fn(u32) -> bool>,
+    /// Whether the duplicates of the documents after the page are excluded from the candidates
+    /// when there are no ranking rules
+    pub exhaustive_distinct_candidates: bool,
+    /// The maximum number of candidates returned, see [`BucketSortOutput::candidates_truncated`]
+    pub max_candidates: Option<u64>,
+    pub scoring_strategy: ScoringStrategy,
+    pub time_budget: TimeBudget,
+    pub ranking_score_threshold: Option<f64>,
+    /// The weight of each ranking rule in the blended scores
+    pub weights: Option<&'a [f64]>,
+    pub normalize_scores: bool,
+    /// Whether the local score of each ranking rule is returned, see [`BucketSortOutput::explanations`]
+    pub explain: bool,
+    /// Whether the details of the buckets are returned for each document, see [`BucketSortOutput::details`]
+    pub details: bool,
+    /// Whether a ranking rule returning a document that was already skipped or returned is reported
+    /// with an error, which keeps all these documents in a bitmap
+    ///
+    /// The universes of the ranking rules already exclude the documents of their previous buckets,
+    /// so it is only enabled by default in the debug builds.
+    pub check_duplicates: bool,
+}
+
+impl Default for BucketSortOptions<'_> {
+    fn default() -> Self {
+        Self {
+            distinct: &[],
+            is_preferred: None,
+            exhaustive_distinct_candidates: false,
+            max_candidates: None,
+            scoring_strategy: ScoringStrategy::default(),
+            time_budget: TimeBudget::max(),
+            ranking_score_threshold: None,
+            weights: None,
+            normalize_scores: false,
+            explain: false,
+            details: false,
+            check_duplicates: cfg!(debug_assertions),
+        }
+    }
+}
+
+/// Sort the universe with the ranking rules and return the `length` documents starting at `from`.
+///
+/// `from` only decides which documents are skipped: the scores of a document only depend on the buckets
+/// in which it was found, so the results of the bucket sorts of several indexes can be merged by comparing
+/// their scores, whatever their offsets. The blended scores are the exception when they are normalized,
+/// as they are relative to the first document of the page.
 #[allow(clippy::too_many_arguments)]
 #[tracing::instrument(level = "trace", skip_all, target = "search::bucket_sort")]
 pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
     ctx: &mut SearchContext<'ctx>,
-    mut ranking_rules: Vec<BoxRankingRule<'ctx, Q>>,
+    ranking_rules: &mut [BoxRankingRule<'ctx, Q>],
     query: &Q,
-    distinct: Option<&str>,
     universe: &RoaringBitmap,
     from: usize,
     length: usize,
-    scoring_strategy: ScoringStrategy,
     logger: &mut dyn SearchLogger<Q>,
-    time_budget: TimeBudget,
-    ranking_score_threshold: Option<f64>,
+    options: BucketSortOptions<'_>,
 ) -> Result<BucketSortOutput> {
+    BucketSorter::new(ctx, ranking_rules, query, universe, from, logger, options)
+        .resumable(false)
+        .next_page(length)
+}
+
+/// An incremental [`bucket_sort`], computing the results page by page.
+///
+/// Each call to [`BucketSorter::next_page`] only does the work needed to compute the requested page,
+/// and resumes from where the previous one stopped, the ranking rules being still in the middle of their iteration.
+/// The pages concatenated are the same as the results of a single [`bucket_sort`] of their combined length,
+/// with the exception of the normalized scores that are normalized page by page.
+pub struct BucketSorter<'a, 'ctx, Q: RankingRuleQueryTrait> {
+    ctx: &'a mut SearchContext<'ctx>,
+    ranking_rules: &'a mut [BoxRankingRule<'ctx, Q>],
+    query: &'a Q,
+    universe: &'a RoaringBitmap,
+    logger: &'a mut dyn SearchLogger<Q>,
+    options: BucketSortOptions<'a>,
+    /// The offset of the next page
+    offset: usize,
+    position: SorterPosition,
+    /// Whether the state of the bucket sort is kept to resume from it for the next page
+    resumable: bool,
+}
+
+impl<'a, 'ctx, Q: RankingRuleQueryTrait> BucketSorter<'a, 'ctx, Q> {
+    /// Prepares a bucket sort starting at the offset `from`, see [`bucket_sort`] for the parameters.
+    pub fn new(
+        ctx: &'a mut SearchContext<'ctx>,
+        ranking_rules: &'a mut [BoxRankingRule<'ctx, Q>],
+        query: &'a Q,
+        universe: &'a RoaringBitmap,
+        from: usize,
+        logger: &'a mut dyn SearchLogger<Q>,
+        options: BucketSortOptions<'a>,
+    ) -> Self {
+        Self {
+            ctx,
+            ranking_rules,
+            query,
+            universe,
+            logger,
+            options,
+            offset: from,
+            position: SorterPosition::Restart,
+            resumable: true,
+        }
+    }
+
+    /// When `false`, the next pages are computed from scratch, which lets the ranking rules skip the documents
+    /// before the page and compute their buckets in batches when a single page is needed, as in [`bucket_sort`].
+    fn resumable(mut self, resumable: bool) -> Self {
+        self.resumable = resumable;
+        self
+    }
+
+    /// Computes the next `length` documents, or less if there are not enough documents left.
+    ///
+    /// Once all the documents were returned, the next pages are empty.
+    #[tracing::instrument(level = "trace", skip_all, target = "search::bucket_sort")]
+    pub fn next_page(&mut self, length: usize) -> Result<BucketSortOutput> {
+        let BucketSortOptions { weights, normalize_scores, explain, .. } = self.options;
+        let mut results = CollectedResults::new(self.options.details);
+        let (summary, position) = bucket_sort_inner(
+            self.ctx,
+            self.ranking_rules,
+            self.query,
+            self.universe,
+            self.offset,
+            length,
+            self.logger,
+            self.options.clone(),
+            &mut PageResults::new(&mut results, self.options.check_duplicates),
+            std::mem::replace(&mut self.position, SorterPosition::Restart),
+            self.resumable,
+        )?;
+        self.offset += results.docids.len();
+        self.position = position;
+        Ok(results.into_output(summary, weights, normalize_scores, explain))
+    }
+}
+
+/// The bucket sort adding the documents of the page starting at `from` to `page_results`, resuming from `position`.
+///
+/// The state of the bucket sort is only kept in the returned position if `resumable` is `true`.
+#[allow(clippy::too_many_arguments)]
+fn bucket_sort_inner<'ctx, Q: RankingRuleQueryTrait>(
+    ctx: &mut SearchContext<'ctx>,
+    ranking_rules: &mut [BoxRankingRule<'ctx, Q>],
+    query: &Q,
+    universe: &RoaringBitmap,
+    from: usize,
+    length: usize,
+    logger: &mut dyn SearchLogger<Q>,
+    options: BucketSortOptions<'_>,
+    page_results: &mut PageResults<'_>,
+    position: SorterPosition,
+    resumable: bool,
+) -> Result<(BucketSortSummary, SorterPosition)> {
+    let BucketSortOptions {
+        distinct,
+        is_preferred,
+        exhaustive_distinct_candidates,
+        max_candidates,
+        scoring_strategy,
+        time_budget,
+        ranking_score_threshold,
+        weights: _,
+        normalize_scores: _,
+        explain: _,
+        details: _,
+        check_duplicates: _,
+    } = options;
+
     logger.initial_query(query);
-    logger.ranking_rules(&ranking_rules);
+    logger.ranking_rules(ranking_rules);
     logger.initial_universe(universe);
 
-    let distinct_field = match distinct {
-        Some(distinct) => Some(distinct),
-        None => ctx.index.distinct_field(ctx.txn)?,
-    };
-
-    let distinct_fid = if let Some(field) = distinct_field {
-        ctx.index.fields_ids_map(ctx.txn)?.id(field)
+    let distinct_fields = if distinct.is_empty() {
+        ctx.index.distinct_field(ctx.txn)?.into_iter().collect()
     } else {
-        None
+        distinct.to_vec()
     };
 
+    let fields_ids_map = ctx.index.fields_ids_map(ctx.txn)?;
+    let mut distinct_fids = Vec::new();
+    for field in distinct_fields {
+        match fields_ids_map.id(field) {
+            Some(fid) => distinct_fids.push(fid),
+            // no document has a value for the field, they are all unique
+            None => {
+                distinct_fids.clear();
+                break;
+            }
+        }
+    }
+
     if universe.len() < from as u64 {
-        return Ok(BucketSortOutput {
-            docids: vec![],
-            scores: vec![],
-            all_candidates: universe.clone(),
+        let mut all_candidates = universe.clone();
+        let candidates_truncated = truncate_candidates(&mut all_candidates, max_candidates, &[]);
+        let summary = BucketSortSummary {
+            bucket_boundaries: vec![],
+            estimated_total_hits: universe.len(),
+            all_candidates,
+            candidates_truncated,
             degraded: false,
-        });
+        };
+        return Ok((summary, SorterPosition::Restart));
     }
     if ranking_rules.is_empty() {
-        if let Some(distinct_fid) = distinct_fid {
+        if !distinct_fids.is_empty() {
             let mut excluded = RoaringBitmap::new();
             let mut results = vec![];
+            let mut distinct_counts = vec![];
+            // the representatives found after the last result, only when the whole universe is scanned
+            let mut representatives_after_results = vec![];
             for docid in universe.iter() {
-                if results.len() >= from + length {
+                let page_is_full = results.len() >= from + length;
+                // the rest of the universe is only scanned to exclude the duplicates from the candidates
+                if page_is_full && !exhaustive_distinct_candidates {
                     break;
                 }
                 if excluded.contains(docid) {
                     continue;
                 }
 
-                distinct_single_docid(ctx.index, ctx.txn, distinct_fid, docid, &mut excluded)?;
-                results.push(docid);
+                let mut docid_excluded = RoaringBitmap::new();
+                distinct_single_docid(
+                    ctx.index,
+                    ctx.txn,
+                    &distinct_fids,
+                    docid,
+                    &mut docid_excluded,
+                )?;
+                docid_excluded -= &excluded;
+                excluded |= &docid_excluded;
+                let representative = match is_preferred {
+                    Some(is_preferred) => {
+                        let group = &docid_excluded & universe;
+                        distinct_representative(is_preferred, docid, universe, &group)
+                    }
+                    None => docid,
+                };
+                if page_is_full {
+                    representatives_after_results.push(representative);
+                    continue;
+                }
+                docid_excluded.remove(representative);
+                let collapsed = docid_excluded & universe;
+                if !collapsed.is_empty() {
+                    logger.distinct_excluded(representative, &collapsed);
+                }
+                results.push(representative);
+                distinct_counts.push(collapsed.len());
             }
 
             let mut all_candidates = universe - excluded;
             all_candidates.extend(results.iter().copied());
+            all_candidates.extend(representatives_after_results);
             // drain the results of the skipped elements
             // this **must** be done **after** writing the entire results in `all_candidates` to ensure
             // e.g. estimatedTotalHits is correct.
             if results.len() >= from {
                 results.drain(..from);
+                distinct_counts.drain(..from);
             } else {
                 results.clear();
+                distinct_counts.clear();
             }
 
-            return Ok(BucketSortOutput {
-                scores: vec![Default::default(); results.len()],
-                docids: results,
+            page_results.extend(&results, &[], &[], &distinct_counts);
+            let estimated_total_hits = all_candidates.len();
+            let candidates_truncated =
+                truncate_candidates(&mut all_candidates, max_candidates, &results);
+            let summary = BucketSortSummary {
+                bucket_boundaries: single_bucket_boundaries(results.len()),
+                estimated_total_hits,
                 all_candidates,
+                candidates_truncated,
                 degraded: false,
-            });
+            };
+            return Ok((summary, SorterPosition::Restart));
         } else {
             let docids: Vec<u32> = universe.iter().skip(from).take(length).collect();
-            return Ok(BucketSortOutput {
-                scores: vec![Default::default(); docids.len()],
-                docids,
-                all_candidates: universe.clone(),
+            page_results.extend(&docids, &[], &[], &vec![0; docids.len()]);
+            let mut all_candidates = universe.clone();
+            let candidates_truncated =
+                truncate_candidates(&mut all_candidates, max_candidates, &docids);
+            let summary = BucketSortSummary {
+                bucket_boundaries: single_bucket_boundaries(docids.len()),
+                estimated_total_hits: universe.len(),
+                all_candidates,
+                candidates_truncated,
                 degraded: false,
-            });
+            };
+            return Ok((summary, SorterPosition::Restart));
         };
     }
 
     let ranking_rules_len = ranking_rules.len();
 
-    logger.start_iteration_ranking_rule(0, ranking_rules[0].as_ref(), query, universe);
+    // when no document can be filtered out, the universe is the exact set of hits
+    let count_preserving = distinct_fids.is_empty()
+        && ranking_score_threshold.is_none()
+        && ranking_rules.iter().all(|rr| rr.is_count_preserving());
 
-    ranking_rules[0].start_iteration(ctx, logger, universe, query)?;
+    // a count-only search doesn't need the order of the documents, only the filtering ranking rules are run.
+    // The documents below the ranking score threshold can only be found with their scores, by sorting them.
+    if length == 0
+        && ranking_score_threshold.is_none()
+        && !ranking_rules.iter().all(|rr| rr.is_count_preserving())
+    {
+        let (mut all_candidates, degraded) =
+            count_candidates(ctx, logger, ranking_rules, query, universe, &time_budget)?;
+        // without the order of the documents, the representatives of the distinct groups are the first
+        // documents of the groups, which keeps the same number of candidates as the sorted search
+        if !distinct_fids.is_empty() {
+            let DistinctOutput { remaining, .. } = apply_distinct_rule(
+                ctx,
+                logger,
+                &distinct_fids,
+                is_preferred,
+                &all_candidates,
+                &all_candidates,
+            )?;
+            all_candidates = remaining;
+        }
+        let estimated_total_hits = all_candidates.len();
+        let candidates_truncated = truncate_candidates(&mut all_candidates, max_candidates, &[]);
+        let summary = BucketSortSummary {
+            bucket_boundaries: vec![],
+            estimated_total_hits,
+            all_candidates,
+            candidates_truncated,
+            degraded,
+        };
+        return Ok((summary, SorterPosition::Restart));
+    }
 
-    let mut ranking_rule_scores: Vec<ScoreDetails> = vec![];
+    let resumed_state = match position {
+        SorterPosition::Exhausted { all_candidates, candidates_truncated } => {
+            let summary = BucketSortSummary {
+                bucket_boundaries: vec![],
+                estimated_total_hits: if count_preserving {
+                    universe.len()
+                } else {
+                    all_candidates.len()
+                },
+                all_candidates: all_candidates.clone(),
+                candidates_truncated,
+                degraded: false,
+            };
+            return Ok((
+                summary,
+                SorterPosition::Exhausted { all_candidates, candidates_truncated },
+            ));
+        }
+        SorterPosition::Resume(state) => Some(*state),
+        SorterPosition::Restart => None,
+    };
 
-    let mut ranking_rule_universes: Vec<RoaringBitmap> =
-        vec![RoaringBitmap::default(); ranking_rules_len];
-    ranking_rule_universes[0].clone_from(universe);
-    let mut cur_ranking_rule_index = 0;
+    let (
+        from,
+        mut ranking_rule_scores,
+        mut ranking_rule_details,
+        mut ranking_rule_universes,
+        mut cur_ranking_rule_index,
+        mut all_candidates,
+        mut candidates_truncated,
+        pending,
+    ) = match resumed_state {
+        Some(state) => (
+            // the documents before the page were already skipped by the previous pages
+            0,
+            state.ranking_rule_scores,
+            state.ranking_rule_details,
+            state.ranking_rule_universes,
+            state.cur_ranking_rule_index,
+            state.all_candidates,
+            state.candidates_truncated,
+            state.leftover,
+        ),
+        None => {
+            logger.start_iteration_ranking_rule(0, ranking_rules[0].as_ref(), query, universe);
+
+            // the order of the skipped documents only matters to pick the distinct representatives,
+            // to remove the documents below the score threshold, and to resume the search
+            if !resumable && distinct_fids.is_empty() && ranking_score_threshold.is_none() {
+                ranking_rules[0].skip_hint(from as u64);
+            }
+            // the first ranking rule starts right away, the data of the next ones is prepared meanwhile
+            #[cfg(feature = "parallel-prefetch")]
+            if ranking_rules[1..].iter().any(|rr| rr.is_prefetchable()) {
+                ctx.prefetch_geo_rtree()?;
+            }
+            ranking_rules[0].start_iteration(ctx, logger, universe, query)?;
+
+            let mut ranking_rule_universes: Vec<RoaringBitmap> =
+                vec![RoaringBitmap::default(); ranking_rules_len];
+            ranking_rule_universes[0].clone_from(universe);
+
+            (from, vec![], vec![], ranking_rule_universes, 0, universe.clone(), false, None)
+        }
+    };
+    let mut exhausted = false;
+
+    // The distinct rule looks for the representatives of the documents among all the candidates,
+    // so they are only truncated at the end of the bucket sort when it is applied.
+    macro_rules! truncate_all_candidates {
+        () => {
+            if distinct_fids.is_empty() {
+                candidates_truncated |= truncate_candidates(
+                    &mut all_candidates,
+                    max_candidates,
+                    page_results.returned(),
+                );
+            }
+        };
+    }
+    truncate_all_candidates!();
+
+    // A ranking rule can't return more non-empty buckets than there are documents in the universe it
+    // started iterating on, otherwise it would make the search loop forever. The empty buckets are not
+    // counted: the graph-based ranking rules return one for each cost that no document matches.
+    let mut ranking_rule_max_buckets: Vec<u64> =
+        ranking_rule_universes.iter().map(|universe| universe.len()).collect();
+    let mut ranking_rule_bucket_counts: Vec<u64> = vec![0; ranking_rules_len];
+    // the number of buckets computed by all the ranking rules, reported with the progress of the search
+    let mut computed_buckets = 0u64;
+    // the buckets of the last ranking rule computed in advance, see `RankingRule::next_buckets_batch`,
+    // and whether the last ranking rule has no more buckets after them
+    let mut batched_buckets = VecDeque::new();
+    let mut batch_exhausted = false;
 
     /// Finish iterating over the current ranking rule, yielding
     /// control to the parent (or finishing the search if not possible).
@@ -129,48 +682,123 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
             );
             ranking_rule_universes[cur_ranking_rule_index].clear();
             ranking_rules[cur_ranking_rule_index].end_iteration(ctx, logger);
+            batched_buckets.clear();
+            batch_exhausted = false;
             if cur_ranking_rule_index == 0 {
+                exhausted = true;
                 break;
             } else {
                 cur_ranking_rule_index -= 1;
             }
             if ranking_rule_scores.len() > cur_ranking_rule_index {
                 ranking_rule_scores.pop();
+                ranking_rule_details.pop();
             }
         };
     }
 
-    let mut all_candidates = universe.clone();
-    let mut valid_docids = vec![];
-    let mut valid_scores = vec![];
+    macro_rules! estimated_total_hits {
+        () => {
+            if count_preserving {
+                universe.len()
+            } else {
+                all_candidates.len()
+            }
+        };
+    }
     let mut cur_offset = 0usize;
+    // the documents of the last bucket that did not fit in the page
+    let mut leftover = None;
+    let mut bucket_boundaries = vec![];
+    // the number of buckets returned by the first ranking rule, and the last one with a boundary
+    let mut top_level_bucket = 0u64;
+    let mut top_level_bucket_with_boundary = None;
+
+    /// Record that a bucket of the first ranking rule starts at `$added_from` if documents
+    /// of a new bucket were just added to the results.
+    macro_rules! record_bucket_boundary {
+        ($added_from:expr) => {
+            if page_results.len() > $added_from
+                && top_level_bucket_with_boundary != Some(top_level_bucket)
+            {
+                bucket_boundaries.push($added_from);
+                top_level_bucket_with_boundary = Some(top_level_bucket);
+            }
+        };
+    }
+
+    // start with the documents that did not fit in the previous page
+    if let Some(pending) = pending {
+        let (remaining, distinct_counts) = maybe_add_to_results(
+            ctx,
+            from,
+            length,
+            logger,
+            page_results,
+            &mut all_candidates,
+            &mut ranking_rule_universes,
+            ranking_rules,
+            cur_ranking_rule_index,
+            &mut cur_offset,
+            &distinct_fids,
+            is_preferred,
+            &pending.ranking_rule_scores,
+            &pending.ranking_rule_details,
+            pending.candidates,
+            pending.distinct_counts,
+        )?;
+        record_bucket_boundary!(0);
+        truncate_all_candidates!();
+        if !remaining.is_empty() {
+            leftover = Some(Leftover { candidates: remaining, distinct_counts, ..pending });
+        }
+    }
 
     macro_rules! maybe_add_to_results {
         ($candidates:expr) => {
-            maybe_add_to_results(
+            let added_from = page_results.len();
+            let (remaining, distinct_counts) = maybe_add_to_results(
                 ctx,
                 from,
                 length,
                 logger,
-                &mut valid_docids,
-                &mut valid_scores,
+                page_results,
                 &mut all_candidates,
                 &mut ranking_rule_universes,
-                &mut ranking_rules,
+                ranking_rules,
                 cur_ranking_rule_index,
                 &mut cur_offset,
-                distinct_fid,
+                &distinct_fids,
+                is_preferred,
                 &ranking_rule_scores,
+                &ranking_rule_details,
                 $candidates,
+                None,
             )?;
+            record_bucket_boundary!(added_from);
+            truncate_all_candidates!();
+            if !remaining.is_empty() {
+                leftover = Some(Leftover {
+                    candidates: remaining,
+                    distinct_counts,
+                    ranking_rule_scores: ranking_rule_scores.clone(),
+                    ranking_rule_details: ranking_rule_details.clone(),
+                });
+            }
         };
     }
 
-    while valid_docids.len() < length {
+    while page_results.len() < length {
+        // the documents to reach are the ones before the page and in it, among the remaining candidates
+        let target = (from + length).min(all_candidates.len() as usize);
+        let progress = if target == 0 { 1. } else { cur_offset.min(target) as f64 / target as f64 };
+        logger.bucket_progress(computed_buckets, progress);
+
         if time_budget.exceeded() {
             loop {
                 let bucket = std::mem::take(&mut ranking_rule_universes[cur_ranking_rule_index]);
                 ranking_rule_scores.push(ScoreDetails::Skipped);
+                ranking_rule_details.push(None);
 
                 // remove candidates from the universe without adding them to result if their score is below the threshold
                 if let Some(ranking_score_threshold) = ranking_score_threshold {
@@ -182,9 +810,13 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
                     }
                 }
 
+                if cur_ranking_rule_index == 0 {
+                    top_level_bucket += 1;
+                }
                 maybe_add_to_results!(bucket);
 
                 ranking_rule_scores.pop();
+                ranking_rule_details.pop();
 
                 if cur_ranking_rule_index == 0 {
                     break;
@@ -193,37 +825,127 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
                 back!();
             }
 
-            return Ok(BucketSortOutput {
-                scores: valid_scores,
-                docids: valid_docids,
+            logger.bucket_progress(computed_buckets, 1.);
+            let estimated_total_hits = estimated_total_hits!();
+            candidates_truncated |=
+                truncate_candidates(&mut all_candidates, max_candidates, page_results.returned());
+            let summary = BucketSortSummary {
+                bucket_boundaries,
+                estimated_total_hits,
                 all_candidates,
+                candidates_truncated,
                 degraded: true,
-            });
+            };
+            return Ok((summary, SorterPosition::Restart));
         }
 
         // The universe for this bucket is zero, so we don't need to sort
         // anything, just go back to the parent ranking rule.
         if ranking_rule_universes[cur_ranking_rule_index].is_empty()
             || (scoring_strategy == ScoringStrategy::Skip
-                && ranking_rule_universes[cur_ranking_rule_index].len() == 1)
+                && ranking_rule_universes[cur_ranking_rule_index].len() == 1
+                && !ranking_rules[cur_ranking_rule_index].is_filtering())
         {
             let bucket = std::mem::take(&mut ranking_rule_universes[cur_ranking_rule_index]);
+            if cur_ranking_rule_index == 0 {
+                top_level_bucket += 1;
+            }
             maybe_add_to_results!(bucket);
             back!();
             continue;
         }
 
-        let Some(next_bucket) = ranking_rules[cur_ranking_rule_index].next_bucket(
-            ctx,
-            logger,
-            &ranking_rule_universes[cur_ranking_rule_index],
-        )?
-        else {
+        // The buckets of the last ranking rule are not sorted any further, they are computed in batches.
+        // The batched buckets are not kept between the pages, so it is only done when not resumable.
+        let next_bucket = if !resumable && cur_ranking_rule_index == ranking_rules_len - 1 {
+            if batched_buckets.is_empty() && !batch_exhausted {
+                // enough buckets to fill the page, as if each of them contained a single document
+                let n = length - page_results.len() + from.saturating_sub(cur_offset);
+                let batch = ranking_rules[cur_ranking_rule_index].next_buckets_batch(
+                    ctx,
+                    logger,
+                    &ranking_rule_universes[cur_ranking_rule_index],
+                    n,
+                )?;
+                batch_exhausted = batch.len() < n;
+                batched_buckets.extend(batch);
+            }
+            batched_buckets.pop_front().map(|bucket: RankingRuleOutput<Q>| (bucket, true))
+        } else {
+            ranking_rules[cur_ranking_rule_index]
+                .next_bucket(ctx, logger, &ranking_rule_universes[cur_ranking_rule_index])?
+                .map(|bucket| (bucket, false))
+        };
+        let Some((mut next_bucket, batched)) = next_bucket else {
+            let leftovers = ranking_rules[cur_ranking_rule_index]
+                .flush_leftovers(&ranking_rule_universes[cur_ranking_rule_index]);
+            if !leftovers.is_empty() {
+                if !ranking_rule_universes[cur_ranking_rule_index].is_superset(&leftovers) {
+                    return Err(InternalError::RankingRuleCandidatesOutsideUniverse {
+                        rule_id: ranking_rules[cur_ranking_rule_index].id(),
+                    }
+                    .into());
+                }
+                ranking_rule_universes[cur_ranking_rule_index] -= &leftovers;
+                computed_buckets += 1;
+                ranking_rule_scores.push(ScoreDetails::Skipped);
+                ranking_rule_details.push(None);
+
+                // the leftovers are the last bucket of the ranking rule, and get the lowest score
+                let below_threshold = ranking_score_threshold.is_some_and(|threshold| {
+                    ScoreDetails::global_score(ranking_rule_scores.iter()) < threshold
+                });
+                if below_threshold {
+                    all_candidates -= &leftovers;
+                } else {
+                    if cur_ranking_rule_index == 0 {
+                        top_level_bucket += 1;
+                    }
+                    maybe_add_to_results!(leftovers);
+                }
+                ranking_rule_scores.pop();
+                ranking_rule_details.pop();
+            }
+            if ranking_rules[cur_ranking_rule_index].is_filtering() {
+                all_candidates -= &ranking_rule_universes[cur_ranking_rule_index];
+            }
             back!();
             continue;
         };
 
+        computed_buckets += 1;
+        if cur_ranking_rule_index == 0 {
+            top_level_bucket += 1;
+        }
+        let len = next_bucket.candidates.len();
+        if len != 0 {
+            ranking_rule_bucket_counts[cur_ranking_rule_index] += 1;
+        }
+        let max_buckets = ranking_rule_max_buckets[cur_ranking_rule_index];
+        debug_assert!(
+            ranking_rule_bucket_counts[cur_ranking_rule_index] <= max_buckets,
+            "the ranking rule {} returned more than {max_buckets} non-empty buckets",
+            ranking_rules[cur_ranking_rule_index].id(),
+        );
+        if ranking_rule_bucket_counts[cur_ranking_rule_index] > max_buckets {
+            return Err(InternalError::RankingRuleTooManyBuckets {
+                rule_id: ranking_rules[cur_ranking_rule_index].id(),
+                max_buckets,
+            }
+            .into());
+        }
+
+        // The distinct rule may have removed documents from the universe since a batch was computed.
+        // Otherwise, a faulty ranking rule must not be able to return documents that are not part of
+        // the search, comparing the lengths is cheaper than checking the universe is a superset.
+        next_bucket.candidates &= &ranking_rule_universes[cur_ranking_rule_index];
+        let dropped = len - next_bucket.candidates.len();
+        if dropped != 0 && !batched {
+            logger.warn_out_of_universe(ranking_rules[cur_ranking_rule_index].as_ref(), dropped);
+        }
+
         ranking_rule_scores.push(next_bucket.score);
+        ranking_rule_details.push(next_bucket.details);
 
         logger.next_bucket_ranking_rule(
             cur_ranking_rule_index,
@@ -231,10 +953,7 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
             &ranking_rule_universes[cur_ranking_rule_index],
             &next_bucket.candidates,
         );
-
-        debug_assert!(
-            ranking_rule_universes[cur_ranking_rule_index].is_superset(&next_bucket.candidates)
-        );
+        logger.bucket_computation(cur_ranking_rule_index, &ranking_rule_scores);
 
         // remove candidates from the universe without adding them to result if their score is below the threshold
         if let Some(ranking_score_threshold) = ranking_score_threshold {
@@ -250,16 +969,24 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
         ranking_rule_universes[cur_ranking_rule_index] -= &next_bucket.candidates;
 
         if cur_ranking_rule_index == ranking_rules_len - 1
-            || (scoring_strategy == ScoringStrategy::Skip && next_bucket.candidates.len() <= 1)
+            || (scoring_strategy == ScoringStrategy::Skip
+                && (next_bucket.candidates.len() <= 1
+                    || next_bucket_skips_child(
+                        ranking_rules[cur_ranking_rule_index + 1].as_ref(),
+                        &next_bucket.candidates,
+                    )))
             || cur_offset + (next_bucket.candidates.len() as usize) < from
         {
             maybe_add_to_results!(next_bucket.candidates);
             ranking_rule_scores.pop();
+            ranking_rule_details.pop();
             continue;
         }
 
         cur_ranking_rule_index += 1;
         ranking_rule_universes[cur_ranking_rule_index].clone_from(&next_bucket.candidates);
+        ranking_rule_max_buckets[cur_ranking_rule_index] = next_bucket.candidates.len();
+        ranking_rule_bucket_counts[cur_ranking_rule_index] = 0;
         logger.start_iteration_ranking_rule(
             cur_ranking_rule_index,
             ranking_rules[cur_ranking_rule_index].as_ref(),
@@ -274,16 +1001,253 @@ pub fn bucket_sort<'ctx, Q: RankingRuleQueryTrait>(
         )?;
     }
 
-    Ok(BucketSortOutput {
-        docids: valid_docids,
-        scores: valid_scores,
+    logger.bucket_progress(computed_buckets, 1.);
+    let estimated_total_hits = estimated_total_hits!();
+    let position = if !resumable {
+        SorterPosition::Restart
+    } else if exhausted {
+        candidates_truncated |=
+            truncate_candidates(&mut all_candidates, max_candidates, page_results.returned());
+        SorterPosition::Exhausted { all_candidates: all_candidates.clone(), candidates_truncated }
+    } else {
+        SorterPosition::Resume(Box::new(BucketSortState {
+            cur_ranking_rule_index,
+            ranking_rule_universes,
+            ranking_rule_scores,
+            ranking_rule_details,
+            all_candidates: all_candidates.clone(),
+            candidates_truncated,
+            leftover,
+        }))
+    };
+
+    // the candidates are truncated after being kept in the position, the distinct rule of the next page needs all of them
+    candidates_truncated |=
+        truncate_candidates(&mut all_candidates, max_candidates, page_results.returned());
+    let summary = BucketSortSummary {
+        bucket_boundaries,
+        estimated_total_hits,
         all_candidates,
+        candidates_truncated,
         degraded: false,
-    })
+    };
+    Ok((summary, position))
+}
+
+/// Keep the `max_candidates` candidates with the smallest ids, if there are more of them.
+///
+/// The `returned` documents are always kept, the facet distribution is computed on the candidates
+/// and must count every document of the results.
+///
+/// Returns whether some candidates were removed.
+fn truncate_candidates(
+    candidates: &mut RoaringBitmap,
+    max_candidates: Option<u64>,
+    returned: &[u32],
+) -> bool {
+    let Some(max_candidates) = max_candidates else {
+        return false;
+    };
+    match u32::try_from(max_candidates).ok().and_then(|max| candidates.select(max)) {
+        Some(first_removed) => {
+            let returned_removed: RoaringBitmap =
+                returned.iter().copied().filter(|docid| candidates.contains(*docid)).collect();
+            candidates.remove_range(first_removed..);
+            *candidates |= returned_removed;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Return the documents of the universe returned by the ranking rules, without sorting them,
+/// along with whether the time budget was exceeded.
+///
+/// The count preserving ranking rules keep the candidates and the query and are not run. The buckets of the
+/// other ones are walked depth first, each bucket being filtered by the next filtering ranking rule with its query.
+/// Once the time budget is exceeded, the documents that were not filtered yet are all kept.
+fn count_candidates<'ctx, Q: RankingRuleQueryTrait>(
+    ctx: &mut SearchContext<'ctx>,
+    logger: &mut dyn SearchLogger<Q>,
+    ranking_rules: &mut [BoxRankingRule<'ctx, Q>],
+    query: &Q,
+    universe: &RoaringBitmap,
+    time_budget: &TimeBudget,
+) -> Result<(RoaringBitmap, bool)> {
+    let Some(filtering) = ranking_rules.iter().position(|rr| !rr.is_count_preserving()) else {
+        return Ok((universe.clone(), false));
+    };
+    let (ranking_rule, next_ranking_rules) = ranking_rules[filtering..].split_first_mut().unwrap();
+
+    ranking_rule.start_iteration(ctx, logger, universe, query)?;
+    let max_buckets = universe.len();
+    let mut non_empty_buckets = 0u64;
+    let mut remaining = universe.clone();
+    let mut candidates = RoaringBitmap::new();
+    let mut degraded = false;
+    while !remaining.is_empty() {
+        if time_budget.exceeded() {
+            candidates |= &remaining;
+            degraded = true;
+            break;
+        }
+        let Some(mut bucket) = ranking_rule.next_bucket(ctx, logger, &remaining)? else {
+            let leftovers = ranking_rule.flush_leftovers(&remaining);
+            if !remaining.is_superset(&leftovers) {
+                return Err(InternalError::RankingRuleCandidatesOutsideUniverse {
+                    rule_id: ranking_rule.id(),
+                }
+                .into());
+            }
+            candidates |= leftovers;
+            break;
+        };
+        let len = bucket.candidates.len();
+        if len != 0 {
+            non_empty_buckets += 1;
+        }
+        if non_empty_buckets > max_buckets {
+            return Err(InternalError::RankingRuleTooManyBuckets {
+                rule_id: ranking_rule.id(),
+                max_buckets,
+            }
+            .into());
+        }
+        bucket.candidates &= &remaining;
+        let dropped = len - bucket.candidates.len();
+        if dropped != 0 {
+            logger.warn_out_of_universe(ranking_rule.as_ref(), dropped);
+        }
+        remaining -= &bucket.candidates;
+
+        let (bucket_candidates, bucket_degraded) = count_candidates(
+            ctx,
+            logger,
+            next_ranking_rules,
+            &bucket.query,
+            &bucket.candidates,
+            time_budget,
+        )?;
+        candidates |= bucket_candidates;
+        degraded |= bucket_degraded;
+    }
+    ranking_rule.end_iteration(ctx, logger);
+
+    Ok((candidates, degraded))
+}
+
+/// Whether the bucket is too small for the child ranking rule to be worth starting an iteration,
+/// see [`RankingRule::min_universe_size`].
+fn next_bucket_skips_child<'ctx, Q: RankingRuleQueryTrait>(
+    child: &dyn RankingRule<'ctx, Q>,
+    bucket: &RoaringBitmap,
+) -> bool {
+    !child.is_filtering() && bucket.len() < child.min_universe_size()
+}
+
+/// The bucket boundaries of documents that are all in the same bucket.
+fn single_bucket_boundaries(docids_len: usize) -> Vec<usize> {
+    if docids_len == 0 {
+        vec![]
+    } else {
+        vec![0]
+    }
+}
+
+/// Compute the global score of each document, weighting the ranking rules and normalizing the scores if needed.
+fn blended_scores(
+    scores: &[Vec<ScoreDetails>],
+    weights: Option<&[f64]>,
+    normalize: bool,
+) -> Vec<f64> {
+    let mut blended_scores: Vec<f64> = scores
+        .iter()
+        .map(|scores| match weights {
+            Some(weights) => ScoreDetails::weighted_global_score(scores.iter(), weights),
+            None => ScoreDetails::global_score(scores.iter()),
+        })
+        .collect();
+    if normalize {
+        normalize_scores(&mut blended_scores);
+    }
+    blended_scores
+}
+
+/// Break down the score of each document by ranking rule, if the scores must be explained.
+fn explanations(scores: &[Vec<ScoreDetails>], explain: bool) -> Vec<HashMap<String, f64>> {
+    if !explain {
+        return vec![];
+    }
+    scores
+        .iter()
+        .map(|scores| {
+            scores
+                .iter()
+                .filter_map(|score| Some((score.name().to_owned(), score.explained_score()?)))
+                .collect()
+        })
+        .collect()
+}
+
+/// The part of the normalized scores that only depends on the rank of the documents.
+const RANK_SCORE_PART: f64 = 0.001;
+
+/// Rescale the scores such that the first one is `1.0` and they strictly decrease in `[0, 1]`,
+/// the documents with the same score being ordered by rank.
+fn normalize_scores(scores: &mut [f64]) {
+    let Some(&top) = scores.first() else { return };
+    let len = scores.len() as f64;
+
+    let mut min_score = 1.0f64;
+    for (rank, score) in scores.iter_mut().enumerate() {
+        let scaled = if top > 0.0 { *score / top } else { 1.0 };
+        // a document can't have a better score than the ones before it
+        min_score = min_score.min(scaled);
+        // the rank part makes the scores strictly decreasing, even for equal scores
+        let rank_score = (len - rank as f64) / len;
+        *score = min_score + (rank_score - min_score) * RANK_SCORE_PART;
+    }
+}
+
+/// Extract the number of matched words from the details of each document.
+fn matched_words(details: &[Vec<RankingRuleDetails>]) -> Vec<usize> {
+    details
+        .iter()
+        .map(|details| {
+            details
+                .iter()
+                .find_map(|detail| match detail {
+                    RankingRuleDetails::Words { matched_words, .. } => Some(*matched_words),
+                    _ => None,
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Extract the query words removed by the terms matching strategy from the details of each document.
+fn dropped_terms(details: &[Vec<RankingRuleDetails>]) -> Vec<Vec<u8>> {
+    details
+        .iter()
+        .map(|details| {
+            details
+                .iter()
+                .find_map(|detail| match detail {
+                    RankingRuleDetails::Words { dropped_terms, .. } => Some(dropped_terms.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default()
+        })
+        .collect()
 }
 
 /// Add the candidates to the results. Take `distinct`, `from`, `length`, and `cur_offset`
 /// into account and inform the logger.
+///
+/// The distinct rule is not applied when the `distinct_counts` of the candidates are given,
+/// as they were already deduplicated.
+///
+/// Returns the candidates that did not fit in the results, along with their distinct counts.
 #[allow(clippy::too_many_arguments)]
 fn maybe_add_to_results<'ctx, Q: RankingRuleQueryTrait>(
     ctx: &mut SearchContext<'ctx>,
@@ -291,8 +1255,7 @@ fn maybe_add_to_results<'ctx, Q: RankingRuleQueryTrait>(
     length: usize,
     logger: &mut dyn SearchLogger<Q>,
 
-    valid_docids: &mut Vec<u32>,
-    valid_scores: &mut Vec<Vec<ScoreDetails>>,
+    page_results: &mut PageResults<'_>,
     all_candidates: &mut RoaringBitmap,
 
     ranking_rule_universes: &mut [RoaringBitmap],
@@ -302,31 +1265,70 @@ fn maybe_add_to_results<'ctx, Q: RankingRuleQueryTrait>(
 
     cur_offset: &mut usize,
 
-    distinct_fid: Option<u16>,
+    distinct_fids: &[u16],
+    is_preferred: Option<&dyn Fn(u32) -> bool>,
     ranking_rule_scores: &[ScoreDetails],
+    ranking_rule_details: &[Option<RankingRuleDetails>],
     candidates: RoaringBitmap,
-) -> Result<()> {
-    // First apply the distinct rule on the candidates, reducing the universes if necessary
-    let candidates = if let Some(distinct_fid) = distinct_fid {
-        let DistinctOutput { remaining, excluded } =
-            apply_distinct_rule(ctx, distinct_fid, &candidates)?;
-        for universe in ranking_rule_universes.iter_mut() {
-            *universe -= &excluded;
+    distinct_counts: Option<Vec<u64>>,
+) -> Result<(RoaringBitmap, Option<Vec<u64>>)> {
+    // First apply the distinct rule on the candidates, reducing the universes if necessary.
+    // The documents of a bucket are ranked equally by all the ranking rules applied so far, so the representatives
+    // keep the scores of the bucket whichever documents they replace: there is no position to score them by.
+    let (candidates, distinct_counts) = match distinct_counts {
+        Some(distinct_counts) => (candidates, Some(distinct_counts)),
+        None if !distinct_fids.is_empty() => {
+            // the documents excluded by the previous buckets are not part of the candidates anymore
+            // and are not counted again
+            let DistinctOutput { remaining, excluded, collapsed } = apply_distinct_rule(
+                ctx,
+                logger,
+                distinct_fids,
+                is_preferred,
+                &candidates,
+                all_candidates,
+            )?;
+            for (index, universe) in ranking_rule_universes.iter_mut().enumerate() {
+                let removed = &*universe & &excluded;
+                if removed.is_empty() {
+                    continue;
+                }
+                *universe -= &removed;
+                // only the ranking rules iterating over their universe are told
+                if index <= cur_ranking_rule_index {
+                    ranking_rules[index].on_universe_shrunk(&removed);
+                }
+            }
             *all_candidates -= &excluded;
+            (remaining, Some(collapsed))
         }
-        remaining
-    } else {
-        candidates.clone()
+        None => (candidates, None),
     };
+
+    // a faulty ranking rule must not be able to return the same document twice,
+    // which would break the pagination
+    if let Some(docid) = page_results.first_seen(&candidates) {
+        return Err(InternalError::RankingRuleDuplicateCandidates {
+            rule_id: ranking_rules[cur_ranking_rule_index].id(),
+            docid,
+        }
+        .into());
+    }
     *all_candidates |= &candidates;
 
     // if the candidates are empty, there is nothing to do;
     if candidates.is_empty() {
-        return Ok(());
+        return Ok((RoaringBitmap::new(), None));
     }
 
+    // the distinct counts of the candidates at the given positions
+    let counts = |range: std::ops::Range<usize>| match &distinct_counts {
+        Some(distinct_counts) => distinct_counts[range].to_vec(),
+        None => vec![0; range.len()],
+    };
+
     // if we still haven't reached the first document to return
-    if *cur_offset < from {
+    let remaining = if *cur_offset < from {
         // and if no document from this bucket can be returned
         if *cur_offset + (candidates.len() as usize) < from {
             // then just skip the bucket
@@ -335,32 +1337,127 @@ fn maybe_add_to_results<'ctx, Q: RankingRuleQueryTrait>(
                 ranking_rules[cur_ranking_rule_index].as_ref(),
                 &candidates,
             );
+            page_results.skip(&candidates);
+            (RoaringBitmap::new(), None)
         } else {
             // otherwise, skip some of the documents and add some of the rest, in order of ids
             let candidates_vec = candidates.iter().collect::<Vec<_>>();
-            let (skipped_candidates, candidates) = candidates_vec.split_at(from - *cur_offset);
+            let skipped = from - *cur_offset;
+            let (skipped_candidates, candidates) = candidates_vec.split_at(skipped);
 
             logger.skip_bucket_ranking_rule(
                 cur_ranking_rule_index,
                 ranking_rules[cur_ranking_rule_index].as_ref(),
                 &skipped_candidates.iter().collect(),
             );
-            let candidates =
-                candidates.iter().take(length - valid_docids.len()).copied().collect::<Vec<_>>();
-            logger.add_to_results(&candidates);
-            valid_docids.extend_from_slice(&candidates);
-            valid_scores
-                .extend(std::iter::repeat(ranking_rule_scores.to_owned()).take(candidates.len()));
+            page_results.skip(skipped_candidates.iter().copied());
+            let (candidates, remaining) =
+                candidates.split_at(candidates.len().min(length - page_results.len()));
+            logger.add_to_results(candidates);
+            let added = skipped + candidates.len();
+            page_results.extend(
+                candidates,
+                ranking_rule_scores,
+                ranking_rule_details,
+                &counts(skipped..added),
+            );
+            let remaining_counts =
+                distinct_counts.is_some().then(|| counts(added..candidates_vec.len()));
+            (remaining.iter().copied().collect(), remaining_counts)
         }
     } else {
         // if we have passed the offset already, add some of the documents (up to the limit)
-        let candidates = candidates.iter().take(length - valid_docids.len()).collect::<Vec<u32>>();
+        let mut candidates_iter = candidates.iter();
+        let candidates =
+            candidates_iter.by_ref().take(length - page_results.len()).collect::<Vec<u32>>();
         logger.add_to_results(&candidates);
-        valid_docids.extend_from_slice(&candidates);
-        valid_scores
-            .extend(std::iter::repeat(ranking_rule_scores.to_owned()).take(candidates.len()));
-    }
+        page_results.extend(
+            &candidates,
+            ranking_rule_scores,
+            ranking_rule_details,
+            &counts(0..candidates.len()),
+        );
+        let remaining: RoaringBitmap = candidates_iter.collect();
+        let remaining_counts = distinct_counts
+            .is_some()
+            .then(|| counts(candidates.len()..candidates.len() + remaining.len() as usize));
+        (remaining, remaining_counts)
+    };
 
     *cur_offset += candidates.len() as usize;
-    Ok(())
+    Ok(remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::hashmap;
+    use roaring::RoaringBitmap;
+
+    use super::{explanations, normalize_scores, CollectedResults, PageResults};
+    use crate::score_details::{Rank, ScoreDetails, Sort, Typo, Words};
+
+    #[test]
+    fn normalized_scores_strictly_decrease() {
+        let mut scores = vec![0.8, 0.8, 0.9, 0.4, 0.4, 0.4, 0.0, 0.0];
+        normalize_scores(&mut scores);
+
+        assert_eq!(scores[0], 1.0);
+        for window in scores.windows(2) {
+            assert!(window[0] > window[1], "{scores:?}");
+        }
+        assert!(scores.iter().all(|score| (0.0..=1.0).contains(score)), "{scores:?}");
+
+        let mut scores = vec![0.0, 0.0];
+        normalize_scores(&mut scores);
+        assert_eq!(scores[0], 1.0);
+        assert!(scores[0] > scores[1]);
+    }
+
+    #[test]
+    fn explanations_break_down_the_scores() {
+        let scores = vec![vec![
+            ScoreDetails::Words(Words { matching_words: 1, max_matching_words: 2 }),
+            ScoreDetails::Sort(Sort {
+                field_name: "price".into(),
+                ascending: true,
+                redacted: false,
+                value: 2.into(),
+            }),
+            ScoreDetails::Typo(Typo { typo_count: 0, max_typo_count: 1 }),
+            ScoreDetails::Proximity(Rank { rank: 1, max_rank: 5 }),
+        ]];
+
+        assert!(explanations(&scores, false).is_empty());
+        // the sort doesn't have a score
+        assert_eq!(
+            explanations(&scores, true),
+            [hashmap! {
+                "words".to_owned() => 0.5,
+                "typo".to_owned() => 1.0,
+                "proximity".to_owned() => 0.2,
+            }]
+        );
+    }
+
+    #[test]
+    fn page_results_remember_the_seen_documents() {
+        let mut sink = CollectedResults::default();
+        let mut page_results = PageResults::new(&mut sink, true);
+        page_results.skip([1, 2]);
+        page_results.extend(&[5, 3], &[], &[], &[0, 0]);
+        assert_eq!(page_results.len(), 2);
+
+        assert_eq!(page_results.first_seen(&RoaringBitmap::from_iter([4, 6])), None);
+        assert_eq!(page_results.first_seen(&RoaringBitmap::from_iter([4, 5])), Some(5));
+        assert_eq!(page_results.first_seen(&RoaringBitmap::from_iter([2, 3])), Some(2));
+        assert_eq!(sink.docids, [5, 3]);
+
+        // without checking the duplicates, only the returned documents are kept
+        let mut sink = CollectedResults::default();
+        let mut page_results = PageResults::new(&mut sink, false);
+        page_results.skip([1, 2]);
+        page_results.extend(&[5, 3], &[], &[], &[0, 0]);
+        assert_eq!(page_results.returned(), [5, 3]);
+        assert_eq!(page_results.first_seen(&RoaringBitmap::from_iter([2, 5])), None);
+    }
 }
@@ -5,6 +5,8 @@ use roaring::RoaringBitmap;
 const FID_SIZE: usize = 2;
 const DOCID_SIZE: usize = 4;
 
+use super::logger::SearchLogger;
+use super::ranking_rules::RankingRuleQueryTrait;
 use crate::heed_codec::facet::{
     FacetGroupKey, FacetGroupKeyCodec, FacetGroupValueCodec, FieldDocIdFacetCodec,
 };
@@ -14,57 +16,131 @@ use crate::{Index, Result, SearchContext};
 pub struct DistinctOutput {
     pub remaining: RoaringBitmap,
     pub excluded: RoaringBitmap,
+    /// The number of documents collapsed into each document of `remaining`, in the order of `remaining`
+    pub collapsed: Vec<u64>,
 }
 
 /// Return a [`DistinctOutput`] containing:
 /// - `remaining`: a set of docids built such that exactly one element from `candidates`
-/// is kept for each distinct value inside the given field. If the field does not exist, it
-/// is considered unique.
-/// - `excluded`: the set of document ids that contain a value for the given field that occurs
-/// in the given candidates.
-pub fn apply_distinct_rule(
+/// is kept for each distinct combination of values inside the given fields. The documents
+/// missing one of the fields are never removed. The kept document is chosen
+/// among the documents of the group in `universe` by [`distinct_representative`],
+/// even if it is not part of the candidates.
+/// - `excluded`: the set of document ids that contain, for each of the given fields,
+/// a value that occurs in the given candidates.
+/// - `collapsed`: for each document of `remaining`, the number of other documents of `universe`
+/// that it excluded and that were not already excluded by a previous document.
+pub fn apply_distinct_rule<Q: RankingRuleQueryTrait>(
     ctx: &mut SearchContext<'_>,
-    field_id: u16,
+    logger: &mut dyn SearchLogger<Q>,
+    field_ids: &[u16],
+    is_preferred: Option<&dyn Fn(u32) -> bool>,
     candidates: &RoaringBitmap,
+    universe: &RoaringBitmap,
 ) -> Result<DistinctOutput> {
     let mut excluded = RoaringBitmap::new();
-    let mut remaining = RoaringBitmap::new();
+    let mut kept = Vec::new();
     for docid in candidates {
         if excluded.contains(docid) {
             continue;
         }
-        distinct_single_docid(ctx.index, ctx.txn, field_id, docid, &mut excluded)?;
-        remaining.push(docid);
+        let mut docid_excluded = RoaringBitmap::new();
+        distinct_single_docid(ctx.index, ctx.txn, field_ids, docid, &mut docid_excluded)?;
+        docid_excluded -= &excluded;
+        excluded |= &docid_excluded;
+        let representative = match is_preferred {
+            Some(is_preferred) => {
+                let group = &docid_excluded & universe;
+                distinct_representative(is_preferred, docid, candidates, &group)
+            }
+            None => docid,
+        };
+        docid_excluded.remove(representative);
+        let collapsed = docid_excluded & universe;
+        if !collapsed.is_empty() {
+            logger.distinct_excluded(representative, &collapsed);
+        }
+        kept.push((representative, collapsed.len()));
+    }
+
+    // the preferred representatives are not necessarily in the order of the candidates
+    kept.sort_unstable_by_key(|(docid, _)| *docid);
+    let remaining = kept.iter().map(|(docid, _)| *docid).collect();
+    let collapsed = kept.into_iter().map(|(_, collapsed)| collapsed).collect();
+    Ok(DistinctOutput { remaining, excluded, collapsed })
+}
+
+/// Return the document of the group that must be kept, `docid` being the best ranked one
+/// and `candidates` the documents ranked in the same bucket as `docid`.
+///
+/// The best ranked document for which `is_preferred` returns `true` is kept, or `docid` if there is none.
+/// The documents of the next buckets are considered ranked in the order of their ids, as their actual
+/// ranking is not known yet.
+pub fn distinct_representative(
+    is_preferred: &dyn Fn(u32) -> bool,
+    docid: u32,
+    candidates: &RoaringBitmap,
+    group: &RoaringBitmap,
+) -> u32 {
+    if is_preferred(docid) {
+        return docid;
     }
-    Ok(DistinctOutput { remaining, excluded })
+    let in_bucket = candidates & group;
+    let next_buckets = group - &in_bucket;
+    in_bucket.iter().chain(&next_buckets).find(|&d| is_preferred(d)).unwrap_or(docid)
 }
 
 /// Apply the distinct rule defined by [`apply_distinct_rule`] for a single document id.
 pub fn distinct_single_docid(
     index: &Index,
     txn: &RoTxn<'_>,
-    field_id: u16,
+    field_ids: &[u16],
     docid: u32,
     excluded: &mut RoaringBitmap,
 ) -> Result<()> {
-    for item in facet_string_values(docid, field_id, index, txn)? {
-        let ((_, _, facet_value), _) = item?;
-        if let Some(facet_docids) = facet_value_docids(
-            index.facet_id_string_docids.remap_types(),
-            txn,
-            field_id,
-            facet_value,
-        )? {
-            *excluded |= facet_docids;
+    // the documents sharing a value with the given document, for all the fields simultaneously
+    let mut same_values: Option<RoaringBitmap> = None;
+    for &field_id in field_ids {
+        let mut field_same_values = RoaringBitmap::new();
+        let mut has_value = false;
+        for item in facet_string_values(docid, field_id, index, txn)? {
+            let ((_, _, facet_value), _) = item?;
+            has_value = true;
+            if let Some(facet_docids) = facet_value_docids(
+                index.facet_id_string_docids.remap_types(),
+                txn,
+                field_id,
+                facet_value,
+            )? {
+                field_same_values |= facet_docids;
+            }
         }
-    }
-    for item in facet_number_values(docid, field_id, index, txn)? {
-        let ((_, _, facet_value), _) = item?;
-        if let Some(facet_docids) =
-            facet_value_docids(index.facet_id_f64_docids.remap_types(), txn, field_id, facet_value)?
-        {
-            *excluded |= facet_docids;
+        for item in facet_number_values(docid, field_id, index, txn)? {
+            let ((_, _, facet_value), _) = item?;
+            has_value = true;
+            if let Some(facet_docids) = facet_value_docids(
+                index.facet_id_f64_docids.remap_types(),
+                txn,
+                field_id,
+                facet_value,
+            )? {
+                field_same_values |= facet_docids;
+            }
+        }
+
+        // a document missing one of the fields is unique
+        if !has_value {
+            return Ok(());
         }
+
+        match &mut same_values {
+            Some(same_values) => *same_values &= field_same_values,
+            None => same_values = Some(field_same_values),
+        }
+    }
+
+    if let Some(same_values) = same_values {
+        *excluded |= same_values;
     }
     Ok(())
 }
@@ -259,6 +259,7 @@ impl State {
                         score: ScoreDetails::ExactAttribute(
                             score_details::ExactAttribute::ExactMatch,
                         ),
+                        details: None,
                     }),
                 )
             }
@@ -280,18 +281,17 @@ impl State {
                         score: ScoreDetails::ExactAttribute(
                             score_details::ExactAttribute::MatchesStart,
                         ),
+                        details: None,
                     }),
                 )
             }
             State::Empty(query_graph) => (
                 State::Empty(query_graph.clone()),
-                Some(RankingRuleOutput {
-                    query: query_graph,
-                    candidates: universe.clone(),
-                    score: ScoreDetails::ExactAttribute(
-                        score_details::ExactAttribute::NoExactMatch,
-                    ),
-                }),
+                Some(RankingRuleOutput::whole(
+                    query_graph,
+                    universe,
+                    ScoreDetails::ExactAttribute(score_details::ExactAttribute::NoExactMatch),
+                )),
             ),
         };
         (state, output)
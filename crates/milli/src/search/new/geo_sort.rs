@@ -1,18 +1,25 @@
-use std::collections::VecDeque;
-use std::iter::FromIterator;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::Arc;
 
 use heed::types::{Bytes, Unit};
 use heed::{RoPrefix, RoTxn};
 use roaring::RoaringBitmap;
-use rstar::RTree;
+use rstar::{Envelope, ParentNode, PointDistance, RTree, RTreeNode, AABB};
 
 use super::facet_string_values;
-use super::ranking_rules::{RankingRule, RankingRuleOutput, RankingRuleQueryTrait};
+#[cfg(test)]
+use super::ranking_rules::PlaceholderQuery;
+use super::ranking_rules::{
+    RankingRule, RankingRuleDetails, RankingRuleOutput, RankingRuleQueryTrait,
+};
+#[cfg(test)]
+use super::DefaultSearchLogger;
 use crate::heed_codec::facet::{FieldDocIdFacetCodec, OrderedF64Codec};
 use crate::score_details::{self, ScoreDetails};
 use crate::{
-    distance_between_two_points, lat_lng_to_xyz, GeoPoint, Index, Result, SearchContext,
-    SearchLogger,
+    antipode, distance_between_two_points, lat_lng_to_xyz, GeoPoint, Index, InternalError, Result,
+    SearchContext, SearchLogger, UserError,
 };
 
 const FID_SIZE: usize = 2;
@@ -42,8 +49,10 @@ fn facet_number_values<'a>(
 }
 
 /// Define the strategy used by the geo sort.
-/// The parameter represents the cache size, and, in the case of the Dynamic strategy,
+/// The parameter represents the size of the first refill of the cache, and, in the case of the Dynamic strategy,
 /// the point where we move from using the iterative strategy to the rtree.
+///
+/// The next refills of an iteration cache twice as many documents as the previous one, up to 16000 documents.
 #[derive(Debug, Clone, Copy)]
 pub enum Strategy {
     AlwaysIterative(usize),
@@ -66,11 +75,43 @@ impl Strategy {
         }
     }
 
+    /// The number of documents cached by the first refill of the cache.
     pub fn cache_size(&self) -> usize {
         match self {
             Strategy::AlwaysIterative(i) | Strategy::AlwaysRtree(i) | Strategy::Dynamic(i) => *i,
         }
     }
+
+    /// The number of candidates from which the Dynamic strategy moves from the iterative strategy to the rtree.
+    pub fn threshold(&self) -> Option<usize> {
+        match self {
+            Strategy::AlwaysIterative(_) | Strategy::AlwaysRtree(_) => None,
+            Strategy::Dynamic(i) => Some(*i),
+        }
+    }
+}
+
+/// The number of documents above which the refills of the cache of the geo sort stop growing.
+const MAX_CACHE_SIZE: usize = 16 * 1000;
+
+/// A unit to convert the distances of the geo sort into, which are always computed in meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceUnit {
+    #[default]
+    Meters,
+    Kilometers,
+    Miles,
+}
+
+impl DistanceUnit {
+    /// Convert a distance expressed in meters into this unit.
+    pub fn convert_from_meters(&self, meters: f64) -> f64 {
+        match self {
+            DistanceUnit::Meters => meters,
+            DistanceUnit::Kilometers => meters / 1_000.,
+            DistanceUnit::Miles => meters / 1_609.344,
+        }
+    }
 }
 
 pub struct GeoSort<Q: RankingRuleQueryTrait> {
@@ -78,12 +119,31 @@ pub struct GeoSort<Q: RankingRuleQueryTrait> {
 
     strategy: Strategy,
     ascending: bool,
-    point: [f64; 2],
+    /// The points from which the distances are computed, a document is sorted by its distance to the nearest one.
+    ///
+    /// There is always at least one point.
+    points: Vec<[f64; 2]>,
     field_ids: Option<[u16; 2]>,
-    rtree: Option<RTree<GeoPoint>>,
-
-    cached_sorted_docids: VecDeque<(u32, [f64; 2])>,
+    rtree: Option<Arc<RTree<GeoPoint>>>,
+    /// The top-left and bottom-right lat-lng corners of the box outside of which documents are not geo sorted.
+    bounding_box: Option<[[f64; 2]; 2]>,
+    /// The distance, in meters, beyond which the documents are removed from the search.
+    ///
+    /// When it is set, the documents that are not geo sorted are removed from the search as well.
+    max_distance: Option<f64>,
+
+    /// The sorted docids with their coordinates and their distance to the nearest of the `points`, see [`cache_order`].
+    cached_sorted_docids: VecDeque<(u32, [f64; 2], f64)>,
+    /// The walks of the rtree from each origin, continued by the next refill of the cache during the current iteration.
+    rtree_walks: Option<Vec<RtreeWalk>>,
+    /// The number of documents cached by the next refill of the cache, during the current iteration.
+    refill_size: usize,
     geo_candidates: RoaringBitmap,
+    /// The number of documents that the bucket sort skips in the next iteration, see [`RankingRule::skip_hint`].
+    skipped: u64,
+    /// Whether all the geo candidates are skipped by the bucket sort in the current iteration,
+    /// in which case they are returned in a single bucket without being sorted.
+    skip_geo_candidates: bool,
 }
 
 impl<Q: RankingRuleQueryTrait> GeoSort<Q> {
@@ -92,116 +152,607 @@ impl<Q: RankingRuleQueryTrait> GeoSort<Q> {
         geo_faceted_docids: RoaringBitmap,
         point: [f64; 2],
         ascending: bool,
+        bounding_box: Option<[[f64; 2]; 2]>,
+        max_distance: Option<f64>,
+    ) -> Result<Self> {
+        Self::with_points(
+            strategy,
+            geo_faceted_docids,
+            vec![point],
+            ascending,
+            bounding_box,
+            max_distance,
+        )
+    }
+
+    /// Create a geo sort that sorts the documents by their distance to the nearest of the given points.
+    ///
+    /// When descending, the documents farthest from all the points come first.
+    ///
+    /// With the rtree, the nearest neighbors of every point are walked at the same time and each point of
+    /// the rtree is met once per query point, which makes a refill roughly `points.len()²` times more expensive.
+    /// The iterative strategy only computes `points.len()` distances per document.
+    ///
+    /// Returns [`UserError::InvalidGeoSort`] if `points` is empty.
+    pub fn with_points(
+        strategy: Strategy,
+        geo_faceted_docids: RoaringBitmap,
+        points: Vec<[f64; 2]>,
+        ascending: bool,
+        bounding_box: Option<[[f64; 2]; 2]>,
+        max_distance: Option<f64>,
     ) -> Result<Self> {
+        if points.is_empty() {
+            return Err(UserError::InvalidGeoSort {
+                reason: "the geo sort needs at least one point".to_owned(),
+            }
+            .into());
+        }
         Ok(Self {
             query: None,
             strategy,
             ascending,
-            point,
+            points,
             geo_candidates: geo_faceted_docids,
             field_ids: None,
             rtree: None,
+            bounding_box,
+            max_distance,
             cached_sorted_docids: VecDeque::new(),
+            rtree_walks: None,
+            refill_size: strategy.cache_size(),
+            skipped: 0,
+            skip_geo_candidates: false,
         })
     }
 
+    /// Double the size of the next refill of the cache, up to the maximum cache size.
+    fn grow_refill_size(&mut self) {
+        let max_cache_size = MAX_CACHE_SIZE.max(self.strategy.cache_size());
+        self.refill_size = self.refill_size.saturating_mul(2).min(max_cache_size);
+    }
+
     /// Refill the internal buffer of cached docids based on the strategy.
     /// Drop the rtree if we don't need it anymore.
     fn fill_buffer(
         &mut self,
         ctx: &mut SearchContext<'_>,
+        logger: &mut dyn SearchLogger<Q>,
         geo_candidates: &RoaringBitmap,
     ) -> Result<()> {
         debug_assert!(self.field_ids.is_some(), "fill_buffer can't be called without the lat&lng");
         debug_assert!(self.cached_sorted_docids.is_empty());
 
         // lazily initialize the rtree if needed by the strategy, and cache it in `self.rtree`
-        let rtree = if self.strategy.use_rtree(geo_candidates.len() as usize) {
-            if let Some(rtree) = self.rtree.as_ref() {
+        let rtree = if self.use_rtree(geo_candidates.len()) {
+            if let Some(rtree) = self.rtree.as_deref() {
                 // get rtree from cache
                 Some(rtree)
             } else {
-                let rtree = ctx.index.geo_rtree(ctx.txn)?.expect("geo candidates but no rtree");
-                // insert rtree in cache and returns it.
-                // Can't use `get_or_insert_with` because getting the rtree from the DB is a fallible operation.
-                Some(&*self.rtree.insert(rtree))
+                // the rtree is shared by all the geo sorts of the search context
+                match ctx.geo_rtree()? {
+                    // insert rtree in cache and returns it.
+                    // Can't use `get_or_insert_with` because getting the rtree from the DB is a fallible operation.
+                    Some(rtree) => Some(&**self.rtree.insert(rtree)),
+                    // the rtree may not be built yet, e.g. while the index is partially reindexed
+                    None if matches!(self.strategy, Strategy::AlwaysRtree(_)) => {
+                        return Err(InternalError::MissingGeoRtree.into());
+                    }
+                    None => {
+                        logger.warning(
+                            "geo candidates but no rtree, falling back to the iterative geo sort",
+                        );
+                        None
+                    }
+                }
             }
         } else {
             None
         };
-
-        let cache_size = self.strategy.cache_size();
-        if let Some(rtree) = rtree {
-            if self.ascending {
-                let point = lat_lng_to_xyz(&self.point);
-                for point in rtree.nearest_neighbor_iter(&point) {
-                    if geo_candidates.contains(point.data.0) {
-                        self.cached_sorted_docids.push_back(point.data);
-                        if self.cached_sorted_docids.len() >= cache_size {
-                            break;
+        logger.geo_sort_strategy(self.strategy, geo_candidates.len(), rtree.is_some());
+
+        let cache_size = self.refill_size;
+        let (targets, ascending, max_distance) =
+            (&self.points[..], self.ascending, self.max_distance);
+        let is_too_far = |distance: f64| max_distance.is_some_and(|max| distance > max);
+        if let (Some(rtree), Some(bounding_box)) = (rtree, self.bounding_box) {
+            // the envelopes are not ordered by distance, we must retrieve all the points and sort them
+            let mut documents = Vec::new();
+            for envelope in bounding_box_envelopes(bounding_box) {
+                for point in rtree.locate_in_envelope_intersecting(&envelope) {
+                    let (id, coord) = point.data;
+                    if geo_candidates.contains(id) && is_in_bounding_box(bounding_box, coord) {
+                        let (_, distance) = nearest_target(targets, coord);
+                        if !is_too_far(distance) {
+                            documents.push((id, coord, distance));
                         }
                     }
                 }
+            }
+
+            // the documents outside of the bounding box or too far are not geo sorted
+            let inside: RoaringBitmap = documents.iter().map(|(id, _, _)| *id).collect();
+            self.geo_candidates -= geo_candidates - inside;
+
+            documents.sort_by(|left, right| cache_order(ascending, left, right));
+            // the cache is emptied from the front when ascending and from the back when descending
+            let documents = if ascending {
+                &documents[..cache_size.min(documents.len())]
             } else {
-                // in the case of the desc geo sort we look for the closest point to the opposite of the queried point
-                // and we insert the points in reverse order they get reversed when emptying the cache later on
-                let point = lat_lng_to_xyz(&opposite_of(self.point));
-                for point in rtree.nearest_neighbor_iter(&point) {
-                    if geo_candidates.contains(point.data.0) {
-                        self.cached_sorted_docids.push_front(point.data);
-                        if self.cached_sorted_docids.len() >= cache_size {
-                            break;
-                        }
+                &documents[documents.len().saturating_sub(cache_size)..]
+            };
+            self.cached_sorted_docids.extend(documents);
+        } else if let Some(rtree) = rtree {
+            // in the case of the desc geo sort we look for the closest point to the opposite of the queried points
+            let origins: Vec<_> = targets
+                .iter()
+                .map(|&target| {
+                    if ascending {
+                        lat_lng_to_xyz(&target)
+                    } else {
+                        lat_lng_to_xyz(&antipode(target))
+                    }
+                })
+                .collect();
+            // the walks continue from where the previous refill of the iteration stopped
+            let mut walks = match self.rtree_walks.take() {
+                Some(walks) => walks,
+                None => origins.iter().map(|&origin| RtreeWalk::new(rtree, origin)).collect(),
+            };
+            let mut visited = 0;
+
+            let mut documents: Vec<(u32, [f64; 2], f64)> = Vec::new();
+            // whether all the points that can be geo sorted were visited
+            let mut reached_end = true;
+            // the nearest neighbors of each origin, merged by increasing distance
+            while let Some(walk) = nearest_walk(rtree, &mut walks) {
+                let Some(step) = walks[walk].pop(rtree) else { break };
+                let point = step.point(rtree);
+                visited += 1;
+                // a point is met once per origin, it is only kept when met from the origin that sorts it:
+                // the nearest one when ascending, and the opposite of the nearest query point when descending
+                if origins.len() > 1 && sorting_origin(&origins, ascending, point.geom()) != walk {
+                    continue;
+                }
+                let (id, coord) = point.data;
+                if !geo_candidates.contains(id) {
+                    continue;
+                }
+                // the distance is always checked from the queried points, even when descending
+                let (_, distance) = nearest_target(targets, coord);
+                if is_too_far(distance) {
+                    // when ascending the points are returned by increasing distance, the next ones are too far as well
+                    if ascending {
+                        break;
                     }
+                    continue;
+                }
+                // the documents at the same distance must be in the same cache to be ordered by docid
+                let last_distance = documents.last().map(|&(_, _, distance)| distance);
+                if documents.len() >= cache_size && last_distance != Some(distance) {
+                    // the point is left to the next refill
+                    walks[walk].push(step);
+                    visited -= 1;
+                    reached_end = false;
+                    break;
                 }
+                documents.push((id, coord, distance));
             }
+            logger.geo_rtree_points_visited(visited);
+            self.rtree_walks = Some(walks);
+
+            if max_distance.is_some() && reached_end {
+                // the documents that were not cached are too far, they are not geo sorted
+                let cached: RoaringBitmap = documents.iter().map(|&(id, _, _)| id).collect();
+                self.geo_candidates -= geo_candidates - cached;
+            }
+
+            documents.sort_by(|left, right| cache_order(ascending, left, right));
+            self.cached_sorted_docids.extend(documents);
         } else {
             // the iterative version
             let [lat, lng] = self.field_ids.unwrap();
 
-            let mut documents = geo_candidates
-                .iter()
-                .map(|id| -> Result<_> { Ok((id, geo_value(id, lat, lng, ctx.index, ctx.txn)?)) })
-                .collect::<Result<Vec<(u32, [f64; 2])>>>()?;
             // computing the distance between two points is expensive thus we cache the result
-            documents
-                .sort_by_cached_key(|(_, p)| distance_between_two_points(&self.point, p) as usize);
+            #[cfg(not(feature = "parallel-geo-sort"))]
+            let sort_points = geo_candidates.iter().map(|id| -> Result<_> {
+                let points = geo_values(id, lat, lng, ctx.index, ctx.txn)?;
+                Ok((id, self.sort_point(points)))
+            });
+            #[cfg(feature = "parallel-geo-sort")]
+            let sort_points = {
+                use rayon::prelude::*;
+
+                // the transaction can't be shared between threads,
+                // we read the points of the documents first and compute their distances in parallel
+                let points = geo_candidates
+                    .iter()
+                    .map(|id| Ok((id, geo_values(id, lat, lng, ctx.index, ctx.txn)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                let (bounding_box, ascending) = (self.bounding_box, self.ascending);
+                let sort_points: Vec<_> = points
+                    .into_par_iter()
+                    .map(|(id, points)| (id, sort_point(targets, bounding_box, ascending, points)))
+                    .collect();
+                sort_points.into_iter().map(Ok::<_, crate::Error>)
+            };
+
+            let mut documents = Vec::new();
+            let mut outside = RoaringBitmap::new();
+            for result in sort_points {
+                let (id, sort_point) = result?;
+                match sort_point {
+                    Some((point, distance)) if !is_too_far(distance) => {
+                        documents.push((id, point, distance))
+                    }
+                    _ => {
+                        outside.insert(id);
+                    }
+                }
+            }
+            // the documents outside of the bounding box or too far are not geo sorted
+            self.geo_candidates -= outside;
+
+            // sorting on the exact distance keeps very close documents in their distance order
+            documents.sort_by(|left, right| cache_order(ascending, left, right));
             self.cached_sorted_docids.extend(documents);
         };
+        self.grow_refill_size();
 
         Ok(())
     }
+
+    /// Return the next cached document that is part of the geo candidates as a single document bucket.
+    fn next_cached_bucket(
+        &mut self,
+        query: &Q,
+        geo_candidates: &RoaringBitmap,
+    ) -> Option<RankingRuleOutput<Q>> {
+        loop {
+            let (id, point, distance) = if self.ascending {
+                self.cached_sorted_docids.pop_front()?
+            } else {
+                self.cached_sorted_docids.pop_back()?
+            };
+            if geo_candidates.contains(id) {
+                let score = ScoreDetails::GeoSort(score_details::GeoSort {
+                    target_point: nearest_target(&self.points, point).0,
+                    ascending: self.ascending,
+                    value: Some(point),
+                });
+                return Some(RankingRuleOutput {
+                    details: Some(RankingRuleDetails::GeoDistance { distance }),
+                    ..RankingRuleOutput::single(query.clone(), id, score)
+                });
+            }
+        }
+    }
+
+    /// Whether the rtree is used to sort the given number of geo candidates, if there is one.
+    pub fn use_rtree(&self, geo_candidates: u64) -> bool {
+        self.strategy.use_rtree(geo_candidates as usize)
+    }
+
+    /// Return the point of the document used to sort it, along with its distance to the nearest target point.
+    fn sort_point(&self, points: Vec<[f64; 2]>) -> Option<([f64; 2], f64)> {
+        sort_point(&self.points, self.bounding_box, self.ascending, points)
+    }
+}
+
+#[cfg(test)]
+impl GeoSort<PlaceholderQuery> {
+    /// Sort the universe with the iterative strategy and with the rtree, and panic if the buckets differ
+    /// or if the rtree is missing.
+    ///
+    /// Both strategies use the cache size of the current one, which is restored afterwards along with
+    /// the geo candidates and the skip hint. Returns the candidates and the score of each bucket.
+    #[track_caller]
+    pub fn assert_strategy_consistency(
+        &mut self,
+        ctx: &mut SearchContext<'_>,
+        universe: &RoaringBitmap,
+    ) -> Vec<(RoaringBitmap, ScoreDetails)> {
+        let (strategy, rtree) = (self.strategy, self.rtree.clone());
+        let (geo_candidates, skipped) = (self.geo_candidates.clone(), self.skipped);
+        let cache_size = strategy.cache_size();
+
+        let mut sort = |geo_sort: &mut Self, strategy| -> Result<Vec<_>> {
+            geo_sort.strategy = strategy;
+            geo_sort.refill_size = strategy.cache_size();
+            geo_sort.geo_candidates.clone_from(&geo_candidates);
+            geo_sort.skipped = skipped;
+
+            let logger = &mut DefaultSearchLogger;
+            geo_sort.start_iteration(ctx, logger, universe, &PlaceholderQuery)?;
+            let mut universe = universe.clone();
+            let mut buckets = Vec::new();
+            while !universe.is_empty() {
+                let Some(bucket) = geo_sort.next_bucket(ctx, logger, &universe)? else {
+                    break;
+                };
+                universe -= &bucket.candidates;
+                buckets.push((bucket.candidates, bucket.score));
+            }
+            geo_sort.end_iteration(ctx, logger);
+            Ok(buckets)
+        };
+
+        let iterative = sort(self, Strategy::AlwaysIterative(cache_size)).unwrap();
+        let rtree_buckets = sort(self, Strategy::AlwaysRtree(cache_size)).unwrap();
+        assert_eq!(iterative, rtree_buckets, "iterative vs rtree");
+
+        self.strategy = strategy;
+        self.refill_size = strategy.cache_size();
+        self.rtree = rtree;
+        iterative
+    }
+}
+
+/// A walk of the rtree returning its points by increasing distance to an origin.
+///
+/// Unlike [`RTree::nearest_neighbor_iter`], the walk doesn't borrow the rtree: the nodes left to visit are
+/// kept by their path from the root, such that the walk is continued by the next refill of the cache of
+/// the [`GeoSort`] instead of visiting the points of the previous refills again.
+struct RtreeWalk {
+    origin: [f64; 3],
+    /// The nodes and points left to visit, the nearest one first
+    queue: BinaryHeap<WalkStep>,
+}
+
+/// A node or a point of the rtree left to visit by a [`RtreeWalk`].
+struct WalkStep {
+    /// The squared distance between the origin of the walk and the envelope of the node, or the point
+    distance_2: f64,
+    /// The position of the node among the children of each of its ancestors, starting from the root
+    path: Vec<u32>,
+}
+
+impl RtreeWalk {
+    fn new(rtree: &RTree<GeoPoint>, origin: [f64; 3]) -> Self {
+        let mut walk = Self { origin, queue: BinaryHeap::new() };
+        walk.push_children(rtree.root(), &[]);
+        walk
+    }
+
+    /// The squared distance between the origin and the next point of the walk, if there is one.
+    ///
+    /// The nodes nearer than the next point are opened, they are never visited again.
+    fn peek(&mut self, rtree: &RTree<GeoPoint>) -> Option<f64> {
+        loop {
+            let step = self.queue.peek()?;
+            match step.node(rtree) {
+                RTreeNode::Leaf(_) => return Some(step.distance_2),
+                RTreeNode::Parent(parent) => {
+                    let step = self.queue.pop()?;
+                    self.push_children(parent, &step.path);
+                }
+            }
+        }
+    }
+
+    /// Remove the next point of the walk, see [`WalkStep::point`].
+    fn pop(&mut self, rtree: &RTree<GeoPoint>) -> Option<WalkStep> {
+        self.peek(rtree)?;
+        self.queue.pop()
+    }
+
+    /// Put back a point removed from the walk, it is the next one again.
+    fn push(&mut self, step: WalkStep) {
+        self.queue.push(step);
+    }
+
+    fn push_children(&mut self, parent: &ParentNode<GeoPoint>, path: &[u32]) {
+        for (position, child) in parent.children().iter().enumerate() {
+            let distance_2 = match child {
+                RTreeNode::Leaf(point) => point.distance_2(&self.origin),
+                RTreeNode::Parent(node) => node.envelope().distance_2(&self.origin),
+            };
+            let path = path.iter().copied().chain([position as u32]).collect();
+            self.queue.push(WalkStep { distance_2, path });
+        }
+    }
 }
 
-/// Extracts the lat and long values from a single document.
+impl WalkStep {
+    fn node<'a>(&self, rtree: &'a RTree<GeoPoint>) -> &'a RTreeNode<GeoPoint> {
+        let (position, ancestors) = self.path.split_last().expect("the root is never a step");
+        let mut parent = rtree.root();
+        for &position in ancestors {
+            match &parent.children()[position as usize] {
+                RTreeNode::Parent(node) => parent = node,
+                RTreeNode::Leaf(_) => unreachable!("the ancestors of a step are nodes"),
+            }
+        }
+        &parent.children()[*position as usize]
+    }
+
+    /// The point of a step returned by [`RtreeWalk::pop`].
+    fn point<'a>(&self, rtree: &'a RTree<GeoPoint>) -> &'a GeoPoint {
+        match self.node(rtree) {
+            RTreeNode::Leaf(point) => point,
+            RTreeNode::Parent(_) => unreachable!("the walk only returns points"),
+        }
+    }
+}
+
+// the steps are ordered by decreasing distance, such that the binary heap pops the nearest one first
+impl Ord for WalkStep {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance_2.total_cmp(&self.distance_2)
+    }
+}
+
+impl PartialOrd for WalkStep {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for WalkStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for WalkStep {}
+
+/// Return the position of the walk whose next point is the nearest to its origin, if any point is left.
+fn nearest_walk(rtree: &RTree<GeoPoint>, walks: &mut [RtreeWalk]) -> Option<usize> {
+    walks
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(walk, steps)| Some((walk, steps.peek(rtree)?)))
+        .min_by(|(_, left), (_, right)| left.total_cmp(right))
+        .map(|(walk, _)| walk)
+}
+
+/// The order of the documents in the cache, by increasing distance.
+///
+/// The cache is emptied from the back when descending, so the documents at the same distance
+/// are ordered such that they are always returned by ascending docid.
+fn cache_order(
+    ascending: bool,
+    (left_id, _, left): &(u32, [f64; 2], f64),
+    (right_id, _, right): &(u32, [f64; 2], f64),
+) -> Ordering {
+    let ids = if ascending { left_id.cmp(right_id) } else { right_id.cmp(left_id) };
+    left.total_cmp(right).then(ids)
+}
+
+/// Return the point of a document used to sort it, along with its distance to the nearest target point.
+///
+/// A document with multiple points is sorted by its closest point when ascending,
+/// and by its farthest one when descending. The points outside of the bounding box are ignored.
+fn sort_point(
+    targets: &[[f64; 2]],
+    bounding_box: Option<[[f64; 2]; 2]>,
+    ascending: bool,
+    mut points: Vec<[f64; 2]>,
+) -> Option<([f64; 2], f64)> {
+    if let Some(bbox) = bounding_box {
+        points.retain(|point| is_in_bounding_box(bbox, *point));
+    }
+
+    let distances = points.into_iter().map(|point| (point, nearest_target(targets, point).1));
+    if ascending {
+        distances.min_by(|(_, left), (_, right)| left.total_cmp(right))
+    } else {
+        distances.max_by(|(_, left), (_, right)| left.total_cmp(right))
+    }
+}
+
+/// Return the target nearest to the point, along with its distance to the point.
+fn nearest_target(targets: &[[f64; 2]], point: [f64; 2]) -> ([f64; 2], f64) {
+    targets
+        .iter()
+        .map(|target| (*target, distance_between_two_points(target, &point)))
+        .min_by(|(_, left), (_, right)| left.total_cmp(right))
+        .expect("the geo sort has at least one target point")
+}
+
+/// Return the index of the origin of the rtree walk from which the point is sorted.
+///
+/// The origins are the query points when ascending, and their opposites when descending.
+/// The point is then sorted from its nearest query point, which is the farthest opposite.
+fn sorting_origin(origins: &[[f64; 3]], ascending: bool, point: &[f64; 3]) -> usize {
+    let distances_2 = origins.iter().map(|origin| {
+        origin.iter().zip(point).map(|(origin, point)| (origin - point).powi(2)).sum::<f64>()
+    });
+    let origin = if ascending {
+        distances_2.enumerate().min_by(|(_, left), (_, right)| left.total_cmp(right))
+    } else {
+        distances_2.enumerate().max_by(|(_, left), (_, right)| left.total_cmp(right))
+    };
+    origin.map_or(0, |(origin, _)| origin)
+}
+
+/// Returns `true` if the given lat-lng point is inside the bounding box.
+///
+/// When the left side of the box is east of its right side the box wraps around the antimeridian.
+fn is_in_bounding_box([top_left, bottom_right]: [[f64; 2]; 2], [lat, lng]: [f64; 2]) -> bool {
+    let [top, left] = top_left;
+    let [bottom, right] = bottom_right;
+    let lat_matches = (bottom..=top).contains(&lat);
+    let lng_matches = if left > right {
+        (left..=180.).contains(&lng) || (-180.0..=right).contains(&lng)
+    } else {
+        (left..=right).contains(&lng)
+    };
+    lat_matches && lng_matches
+}
+
+/// Returns the envelopes of the rtree containing the points of the bounding box.
+///
+/// The rtree stores the points on the unit sphere, so we compute the smallest cartesian box
+/// that contains the lat-lng box. A bounding box crossing the antimeridian is split in two envelopes.
+fn bounding_box_envelopes([top_left, bottom_right]: [[f64; 2]; 2]) -> Vec<AABB<[f64; 3]>> {
+    let [top, left] = top_left;
+    let [bottom, right] = bottom_right;
+    let lng_ranges =
+        if left > right { vec![(left, 180.), (-180., right)] } else { vec![(left, right)] };
+
+    lng_ranges
+        .into_iter()
+        .map(|(left, right)| {
+            // The extrema of the cartesian coordinates are either on the corners of the box,
+            // on the equator, or on the meridians multiple of 90°.
+            let lats = [bottom, top, 0.].into_iter().filter(|lat| (bottom..=top).contains(lat));
+            let lngs = [left, right, -180., -90., 0., 90., 180.];
+            let lngs: Vec<_> =
+                lngs.into_iter().filter(|lng| (left..=right).contains(lng)).collect();
+
+            let mut min = [f64::INFINITY; 3];
+            let mut max = [f64::NEG_INFINITY; 3];
+            for lat in lats {
+                for &lng in &lngs {
+                    let xyz = lat_lng_to_xyz(&[lat, lng]);
+                    min = std::array::from_fn(|i| min[i].min(xyz[i]));
+                    max = std::array::from_fn(|i| max[i].max(xyz[i]));
+                }
+            }
+            // the conversion to cartesian coordinates isn't exact, be a bit more permissive
+            AABB::from_corners(min.map(|c| c - f64::EPSILON), max.map(|c| c + f64::EPSILON))
+        })
+        .collect()
+}
+
+/// Extracts all the lat and long values from a single document.
+///
+/// If it is not able to find them in the facet number index it will extract them
+/// from the facet string index and parse them as f64 (as the geo extraction behaves).
 ///
-/// If it is not able to find it in the facet number index it will extract it
-/// from the facet string index and parse it as f64 (as the geo extraction behaves).
-fn geo_value(
+/// Returns an error if the document doesn't contain any coordinates.
+fn geo_values(
     docid: u32,
     field_lat: u16,
     field_lng: u16,
     index: &Index,
     rtxn: &RoTxn<'_>,
-) -> Result<[f64; 2]> {
-    let extract_geo = |geo_field: u16| -> Result<f64> {
-        match facet_number_values(docid, geo_field, index, rtxn)?.next() {
-            Some(Ok(((_, _, geo), ()))) => Ok(geo),
-            Some(Err(e)) => Err(e.into()),
-            None => match facet_string_values(docid, geo_field, index, rtxn)?.next() {
-                Some(Ok((_, geo))) => {
-                    Ok(geo.parse::<f64>().expect("cannot parse geo field as f64"))
-                }
-                Some(Err(e)) => Err(e.into()),
-                None => panic!("A geo faceted document doesn't contain any lat or lng"),
-            },
+) -> Result<Vec<[f64; 2]>> {
+    let extract_geo = |geo_field: u16| -> Result<Vec<f64>> {
+        let mut values = Vec::new();
+        for result in facet_number_values(docid, geo_field, index, rtxn)? {
+            let ((_, _, geo), ()) = result?;
+            values.push(geo);
+        }
+        if values.is_empty() {
+            for result in facet_string_values(docid, geo_field, index, rtxn)? {
+                let (_, geo) = result?;
+                values.push(geo.parse::<f64>().expect("cannot parse geo field as f64"));
+            }
         }
+        Ok(values)
     };
 
-    let lat = extract_geo(field_lat)?;
-    let lng = extract_geo(field_lng)?;
+    let lats = extract_geo(field_lat)?;
+    let lngs = extract_geo(field_lng)?;
 
-    Ok([lat, lng])
+    let points: Vec<_> = lats.into_iter().zip(lngs).map(|(lat, lng)| [lat, lng]).collect();
+    if points.is_empty() {
+        return Err(InternalError::GeoFacetedDocumentWithoutCoordinates { docid }.into());
+    }
+
+    Ok(points)
 }
 
 impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for GeoSort<Q> {
@@ -209,11 +760,24 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for GeoSort<Q> {
         "geo_sort".to_owned()
     }
 
+    fn is_count_preserving(&self) -> bool {
+        self.max_distance.is_none()
+    }
+
+    fn is_filtering(&self) -> bool {
+        self.max_distance.is_some()
+    }
+
+    fn is_prefetchable(&self) -> bool {
+        // the rtree may be used unless the iterative strategy is forced
+        !matches!(self.strategy, Strategy::AlwaysIterative(_))
+    }
+
     #[tracing::instrument(level = "trace", skip_all, target = "search::geo_sort")]
     fn start_iteration(
         &mut self,
         ctx: &mut SearchContext<'ctx>,
-        _logger: &mut dyn SearchLogger<Q>,
+        logger: &mut dyn SearchLogger<Q>,
         universe: &RoaringBitmap,
         query: &Q,
     ) -> Result<()> {
@@ -222,21 +786,37 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for GeoSort<Q> {
         self.query = Some(query.clone());
 
         let geo_candidates = &self.geo_candidates & universe;
+        let skipped = std::mem::take(&mut self.skipped);
 
         if geo_candidates.is_empty() {
             return Ok(());
         }
 
+        // the order of the geo candidates doesn't matter when they are all skipped, as long as they
+        // are all geo sorted before the other documents, which is not the case with a bounding box
+        if skipped >= geo_candidates.len()
+            && self.max_distance.is_none()
+            && self.bounding_box.is_none()
+        {
+            self.skip_geo_candidates = true;
+            return Ok(());
+        }
+
         let fid_map = ctx.index.fields_ids_map(ctx.txn)?;
-        let lat = fid_map.id("_geo.lat").expect("geo candidates but no fid for lat");
-        let lng = fid_map.id("_geo.lng").expect("geo candidates but no fid for lng");
+        let inconsistent = |reason| InternalError::InconsistentGeoFields {
+            candidates: geo_candidates.len(),
+            reason,
+        };
+        let lat =
+            fid_map.id("_geo.lat").ok_or_else(|| inconsistent("no field id for `_geo.lat`"))?;
+        let lng =
+            fid_map.id("_geo.lng").ok_or_else(|| inconsistent("no field id for `_geo.lng`"))?;
         self.field_ids = Some([lat, lng]);
-        self.fill_buffer(ctx, &geo_candidates)?;
+        self.fill_buffer(ctx, logger, &geo_candidates)?;
         Ok(())
     }
 
     #[tracing::instrument(level = "trace", skip_all, target = "search::geo_sort")]
-    #[allow(clippy::only_used_in_recursion)]
     fn next_bucket(
         &mut self,
         ctx: &mut SearchContext<'ctx>,
@@ -248,62 +828,162 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for GeoSort<Q> {
         let geo_candidates = &self.geo_candidates & universe;
 
         if geo_candidates.is_empty() {
+            // the documents that are not geo sorted are removed when there is a maximum distance
+            if self.max_distance.is_some() {
+                return Ok(None);
+            }
+            let score = ScoreDetails::GeoSort(score_details::GeoSort {
+                target_point: self.points[0],
+                ascending: self.ascending,
+                value: None,
+            });
+            return Ok(Some(RankingRuleOutput::whole(query, universe, score)));
+        }
+
+        if self.skip_geo_candidates {
             return Ok(Some(RankingRuleOutput {
                 query,
-                candidates: universe.clone(),
+                candidates: geo_candidates,
                 score: ScoreDetails::GeoSort(score_details::GeoSort {
-                    target_point: self.point,
+                    target_point: self.points[0],
                     ascending: self.ascending,
                     value: None,
                 }),
+                details: None,
             }));
         }
 
-        let ascending = self.ascending;
-        let next = |cache: &mut VecDeque<_>| {
-            if ascending {
-                cache.pop_front()
-            } else {
-                cache.pop_back()
-            }
-        };
-        while let Some((id, point)) = next(&mut self.cached_sorted_docids) {
-            if geo_candidates.contains(id) {
-                return Ok(Some(RankingRuleOutput {
-                    query,
-                    candidates: RoaringBitmap::from_iter([id]),
-                    score: ScoreDetails::GeoSort(score_details::GeoSort {
-                        target_point: self.point,
-                        ascending: self.ascending,
-                        value: Some(point),
-                    }),
-                }));
-            }
+        if let Some(bucket) = self.next_cached_bucket(&query, &geo_candidates) {
+            return Ok(Some(bucket));
         }
 
-        // if we got out of this loop it means we've exhausted our cache.
+        // if we got here it means we've exhausted our cache.
         // we need to refill it and run the function again.
-        self.fill_buffer(ctx, &geo_candidates)?;
+        self.fill_buffer(ctx, logger, &geo_candidates)?;
         self.next_bucket(ctx, logger, universe)
     }
 
+    /// Return the cached documents as single document buckets, without intersecting
+    /// the geo candidates with the universe for each of them.
+    fn next_buckets_batch(
+        &mut self,
+        ctx: &mut SearchContext<'ctx>,
+        logger: &mut dyn SearchLogger<Q>,
+        universe: &RoaringBitmap,
+        n: usize,
+    ) -> Result<Vec<RankingRuleOutput<Q>>> {
+        let mut universe = universe.clone();
+        let mut buckets = Vec::new();
+        while buckets.len() < n && !universe.is_empty() {
+            let geo_candidates = &self.geo_candidates & &universe;
+            if !geo_candidates.is_empty() {
+                let query = self.query.as_ref().unwrap().clone();
+                while buckets.len() < n {
+                    let Some(bucket) = self.next_cached_bucket(&query, &geo_candidates) else {
+                        break;
+                    };
+                    universe -= &bucket.candidates;
+                    buckets.push(bucket);
+                }
+                if buckets.len() == n {
+                    break;
+                }
+            }
+
+            // the cache must be refilled, or the documents are not geo sorted
+            let Some(bucket) = self.next_bucket(ctx, logger, &universe)? else {
+                break;
+            };
+            universe -= &bucket.candidates;
+            buckets.push(bucket);
+        }
+        Ok(buckets)
+    }
+
     #[tracing::instrument(level = "trace", skip_all, target = "search::geo_sort")]
     fn end_iteration(&mut self, _ctx: &mut SearchContext<'ctx>, _logger: &mut dyn SearchLogger<Q>) {
         // we do not reset the rtree here, it could be used in a next iteration
         self.query = None;
         self.cached_sorted_docids.clear();
+        self.rtree_walks = None;
+        self.refill_size = self.strategy.cache_size();
+        self.skip_geo_candidates = false;
+    }
+
+    fn skip_hint(&mut self, skipped: u64) {
+        self.skipped = skipped;
+    }
+
+    fn on_universe_shrunk(&mut self, removed: &RoaringBitmap) {
+        self.cached_sorted_docids.retain(|(id, _, _)| !removed.contains(*id));
     }
 }
 
-/// Compute the antipodal coordinate of `coord`
-fn opposite_of(mut coord: [f64; 2]) -> [f64; 2] {
-    coord[0] *= -1.;
-    // in the case of x,0 we want to return x,180
-    if coord[1] > 0. {
-        coord[1] -= 180.;
-    } else {
-        coord[1] += 180.;
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::{antipode, distance_between_two_points, great_circle_bearing};
+
+    #[test]
+    fn antipode_stays_in_range() {
+        assert_eq!(antipode([12., 0.]), [-12., 180.]);
+        assert_eq!(antipode([90., 0.]), [-90., 180.]);
+        assert_eq!(antipode([-90., 0.]), [90., 180.]);
+        assert_eq!(antipode([0., 180.]), [-0., 0.]);
+        assert_eq!(antipode([0., -180.]), [-0., 0.]);
+        assert_eq!(antipode([45., 90.]), [-45., -90.]);
+        assert_eq!(antipode([45., -90.]), [-45., 90.]);
+
+        for lng in -180..=180 {
+            let [_, opposite] = antipode([0., lng as f64]);
+            assert!(opposite > -180. && opposite <= 180., "{lng} gave {opposite}");
+        }
+    }
+
+    #[test]
+    fn bearing_is_clockwise_from_the_north() {
+        let center = [0., 0.];
+        assert_eq!(great_circle_bearing(&center, &center), None);
+        assert_eq!(great_circle_bearing(&center, &[1., 0.]), Some(0.));
+        assert!((great_circle_bearing(&center, &[0., 1.]).unwrap() - 90.).abs() < 1e-9);
+        assert!((great_circle_bearing(&center, &[-1., 0.]).unwrap() - 180.).abs() < 1e-9);
+        assert!((great_circle_bearing(&center, &[0., -1.]).unwrap() - 270.).abs() < 1e-9);
+
+        // slightly west of the north wraps to just below 360°
+        let north_west = great_circle_bearing(&center, &[1., -0.001]).unwrap();
+        assert!(north_west > 359.9 && north_west < 360., "{north_west}");
+
+        // across the antimeridian, the east is still at 90°
+        assert!((great_circle_bearing(&[0., 179.5], &[0., -179.5]).unwrap() - 90.).abs() < 1e-9);
     }
 
-    coord
+    #[test]
+    fn antipode_reverses_the_distance_order() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut random_point = || [rng.gen_range(-90.0..=90.0), rng.gen_range(-180.0..=180.0)];
+
+        for _ in 0..20 {
+            let point = random_point();
+            let opposite = antipode(point);
+            let others: Vec<[f64; 2]> = (0..50).map(|_| random_point()).collect();
+
+            let mut by_distance: Vec<_> = (0..others.len()).collect();
+            by_distance.sort_by(|&a, &b| {
+                let a = distance_between_two_points(&point, &others[a]);
+                let b = distance_between_two_points(&point, &others[b]);
+                a.total_cmp(&b)
+            });
+
+            let mut by_antipode_distance: Vec<_> = (0..others.len()).collect();
+            by_antipode_distance.sort_by(|&a, &b| {
+                let a = distance_between_two_points(&opposite, &others[a]);
+                let b = distance_between_two_points(&opposite, &others[b]);
+                b.total_cmp(&a)
+            });
+
+            assert_eq!(by_distance, by_antipode_distance, "for the point {point:?}");
+        }
+    }
 }
@@ -36,6 +36,7 @@ That is we find the documents where either:
 - OR: `pretty` is 2-close to `house` AND `house` is 1-close to `by`
 */
 
+use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::ops::ControlFlow;
 
@@ -43,46 +44,123 @@ use roaring::RoaringBitmap;
 
 use super::interner::{Interned, MappedInterner};
 use super::logger::SearchLogger;
-use super::query_graph::QueryNode;
+use super::query_graph::{QueryNode, QueryNodeData};
 use super::ranking_rule_graph::{
     ConditionDocIdsCache, DeadEndsCache, ExactnessGraph, FidGraph, PositionGraph, ProximityGraph,
     RankingRuleGraph, RankingRuleGraphTrait, TypoGraph, WordsGraph,
 };
+use super::resolve_query_graph::compute_query_graph_docids;
 use super::small_bitmap::SmallBitmap;
 use super::{QueryGraph, RankingRule, RankingRuleOutput, SearchContext};
-use crate::score_details::Rank;
+use crate::score_details::{BoostedWords, Rank, ScoreDetails};
 use crate::search::new::query_term::LocatedQueryTermSubset;
 use crate::search::new::ranking_rule_graph::PathVisitor;
-use crate::{Result, TermsMatchingStrategy};
+use crate::{Result, TermsMatchingStrategy, UserError};
+
+/// The order in which the `words` ranking rule removes the words of the query, from the first removed to the last one.
+///
+/// The [`TermsMatchingStrategy`] is the built-in implementation, other orders can be given to [`Words::new`].
+pub trait WordRelaxationStrategy {
+    /// Returns the nodes of the query graph removed at each relaxation step. The nodes removed
+    /// at a step stay removed at the next ones, each step gives the documents of a bucket.
+    fn removal_order(
+        &self,
+        ctx: &mut SearchContext<'_>,
+        query_graph: &QueryGraph,
+    ) -> Result<Vec<SmallBitmap<QueryNode>>>;
+
+    /// Whether no word is ever removed, such that all the documents matching the query have the same score
+    /// and are returned in a single bucket, without building the ranking rule graph.
+    fn never_removes_words(&self) -> bool {
+        false
+    }
+}
+
+impl WordRelaxationStrategy for TermsMatchingStrategy {
+    fn removal_order(
+        &self,
+        ctx: &mut SearchContext<'_>,
+        query_graph: &QueryGraph,
+    ) -> Result<Vec<SmallBitmap<QueryNode>>> {
+        Ok(match *self {
+            TermsMatchingStrategy::Last => {
+                query_graph.removal_order_for_terms_matching_strategy_last(ctx)
+            }
+            TermsMatchingStrategy::First => {
+                query_graph.removal_order_for_terms_matching_strategy_first(ctx)
+            }
+            TermsMatchingStrategy::Frequency => {
+                query_graph.removal_order_for_terms_matching_strategy_frequency(ctx)?
+            }
+            TermsMatchingStrategy::MinMatch(min_match) => {
+                query_graph.removal_order_for_terms_matching_strategy_min_match(ctx, min_match)
+            }
+            TermsMatchingStrategy::All => vec![],
+        })
+    }
+
+    fn never_removes_words(&self) -> bool {
+        matches!(self, TermsMatchingStrategy::All)
+    }
+}
 
 pub type Words = GraphBasedRankingRule<WordsGraph>;
 impl GraphBasedRankingRule<WordsGraph> {
-    pub fn new(terms_matching_strategy: TermsMatchingStrategy) -> Self {
-        Self::new_with_id("words".to_owned(), Some(terms_matching_strategy))
+    /// Creates the `words` ranking rule, removing the query words in the order of the relaxation strategy.
+    ///
+    /// The query words whose term ids are in `removed_term_ids` are removed from the query graph
+    /// before the first bucket, as if they had already been removed by the relaxation strategy.
+    /// The relaxation then continues from this graph, and its removal steps are the only ones counted in the scores.
+    pub fn new(
+        relaxation_strategy: Box<dyn WordRelaxationStrategy>,
+        max_relaxation_steps: Option<usize>,
+        removed_term_ids: BTreeSet<u8>,
+    ) -> Self {
+        // no word can be removed, all the documents matching the query have the same score
+        let single_bucket = relaxation_strategy.never_removes_words();
+        let mut words = Self::new_with_id("words".to_owned(), Some(relaxation_strategy));
+        words.max_relaxation_steps = max_relaxation_steps;
+        words.removed_term_ids = removed_term_ids;
+        words.single_bucket = single_bucket;
+        words
+    }
+
+    /// Widen the score of the documents matching all the query words, before any relaxation, such that it is
+    /// at least `exact_boost` above the score of the documents of the first relaxation step, see [`BoostedWords`].
+    ///
+    /// The order of the documents doesn't change, the scores are reported as [`ScoreDetails::BoostedWords`].
+    ///
+    /// Returns [`UserError::InvalidExactBoost`] if the exact boost is not between `0.0`, included, and `1.0`, excluded.
+    pub(crate) fn with_exact_boost(mut self, exact_boost: f64) -> Result<Self> {
+        if !(0.0..1.0).contains(&exact_boost) {
+            return Err(UserError::InvalidExactBoost { exact_boost }.into());
+        }
+        self.exact_boost = Some(exact_boost);
+        Ok(self)
     }
 }
 pub type Proximity = GraphBasedRankingRule<ProximityGraph>;
 impl GraphBasedRankingRule<ProximityGraph> {
     pub fn new(terms_matching_strategy: Option<TermsMatchingStrategy>) -> Self {
-        Self::new_with_id("proximity".to_owned(), terms_matching_strategy)
+        Self::new_with_id("proximity".to_owned(), boxed_strategy(terms_matching_strategy))
     }
 }
 pub type Fid = GraphBasedRankingRule<FidGraph>;
 impl GraphBasedRankingRule<FidGraph> {
     pub fn new(terms_matching_strategy: Option<TermsMatchingStrategy>) -> Self {
-        Self::new_with_id("fid".to_owned(), terms_matching_strategy)
+        Self::new_with_id("fid".to_owned(), boxed_strategy(terms_matching_strategy))
     }
 }
 pub type Position = GraphBasedRankingRule<PositionGraph>;
 impl GraphBasedRankingRule<PositionGraph> {
     pub fn new(terms_matching_strategy: Option<TermsMatchingStrategy>) -> Self {
-        Self::new_with_id("position".to_owned(), terms_matching_strategy)
+        Self::new_with_id("position".to_owned(), boxed_strategy(terms_matching_strategy))
     }
 }
 pub type Typo = GraphBasedRankingRule<TypoGraph>;
 impl GraphBasedRankingRule<TypoGraph> {
     pub fn new(terms_matching_strategy: Option<TermsMatchingStrategy>) -> Self {
-        Self::new_with_id("typo".to_owned(), terms_matching_strategy)
+        Self::new_with_id("typo".to_owned(), boxed_strategy(terms_matching_strategy))
     }
 }
 pub type Exactness = GraphBasedRankingRule<ExactnessGraph>;
@@ -92,18 +170,82 @@ impl GraphBasedRankingRule<ExactnessGraph> {
     }
 }
 
+fn boxed_strategy(
+    terms_matching_strategy: Option<TermsMatchingStrategy>,
+) -> Option<Box<dyn WordRelaxationStrategy>> {
+    terms_matching_strategy.map(|strategy| Box::new(strategy) as Box<dyn WordRelaxationStrategy>)
+}
+
 /// A generic graph-based ranking rule
 pub struct GraphBasedRankingRule<G: RankingRuleGraphTrait> {
     id: String,
-    terms_matching_strategy: Option<TermsMatchingStrategy>,
+    /// The order in which the query words are removed, only given to the `words` ranking rule
+    relaxation_strategy: Option<Box<dyn WordRelaxationStrategy>>,
+    /// The maximum number of removal steps of the terms matching strategy, after which the
+    /// remaining removals are collapsed into a single last bucket.
+    max_relaxation_steps: Option<usize>,
+    /// The term ids of the query words removed from the query graph before the first bucket
+    removed_term_ids: BTreeSet<u8>,
+    /// The query graph given to `start_iteration`, when some of its words were removed before the first bucket
+    original_query_graph: Option<QueryGraph>,
     // When the ranking rule is not iterating over its buckets,
     // its state is `None`.
     state: Option<GraphBasedRankingRuleState<G>>,
+    /// Whether the ranking rule returns all the documents matching the query graph in a single bucket.
+    ///
+    /// The ranking rule graph is then never built, and the documents are computed from the query graph directly.
+    single_bucket: bool,
+    /// The query graph of the single bucket, until the bucket is returned.
+    single_bucket_query: Option<QueryGraph>,
+    /// The minimum gap between the score of the documents matching all the query words and the other ones,
+    /// only given to the `words` ranking rule
+    exact_boost: Option<f64>,
 }
 impl<G: RankingRuleGraphTrait> GraphBasedRankingRule<G> {
     /// Creates the ranking rule with the given identifier
-    pub fn new_with_id(id: String, terms_matching_strategy: Option<TermsMatchingStrategy>) -> Self {
-        Self { id, terms_matching_strategy, state: None }
+    pub fn new_with_id(
+        id: String,
+        relaxation_strategy: Option<Box<dyn WordRelaxationStrategy>>,
+    ) -> Self {
+        Self {
+            id,
+            relaxation_strategy,
+            max_relaxation_steps: None,
+            removed_term_ids: BTreeSet::new(),
+            original_query_graph: None,
+            state: None,
+            single_bucket: false,
+            single_bucket_query: None,
+            exact_boost: None,
+        }
+    }
+
+    /// The score of the buckets of the given rank, widened by the exact boost if there is one.
+    fn score(&self, rank: Rank) -> Result<ScoreDetails> {
+        Ok(match (G::rank_to_score(rank), self.exact_boost) {
+            (ScoreDetails::Words(words), Some(exact_boost)) => {
+                ScoreDetails::BoostedWords(BoostedWords::new(words, exact_boost)?)
+            }
+            (score, _) => score,
+        })
+    }
+
+    /// Returns the documents matching the query graph in a single bucket, with the maximum score.
+    fn next_single_bucket(
+        &mut self,
+        ctx: &mut SearchContext<'_>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<QueryGraph>>> {
+        let Some(query_graph) = self.single_bucket_query.take() else { return Ok(None) };
+
+        // same as the cost of the single bucket computed from the ranking rule graph
+        let next_max_cost = 1 + query_graph.words_in_phrases_count(ctx).saturating_sub(1) as u32;
+        let score = self.score(Rank { rank: next_max_cost, max_rank: next_max_cost })?;
+        let candidates = compute_query_graph_docids(ctx, &query_graph, universe)?;
+        let original_query_graph = self.original_query_graph.as_ref().unwrap_or(&query_graph);
+        let details = G::bucket_details(original_query_graph, &query_graph);
+
+        Ok(Some(RankingRuleOutput { query: query_graph, candidates, score, details }))
     }
 }
 
@@ -121,6 +263,8 @@ pub struct GraphBasedRankingRuleState<G: RankingRuleGraphTrait> {
     cur_cost: u64,
     /// One above the highest possible cost for this rule
     next_max_cost: u64,
+    /// The query graph of the last bucket, when the removal steps were capped by `max_relaxation_steps`
+    collapsed_query_graph: Option<QueryGraph>,
 }
 
 impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBasedRankingRule<G> {
@@ -136,13 +280,50 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
         _universe: &RoaringBitmap,
         query_graph: &QueryGraph,
     ) -> Result<()> {
+        // the removal order and the query graph of each bucket are proportional to the number of nodes
+        let nodes = query_graph.nodes.len() as usize;
+        if self.relaxation_strategy.is_some() && nodes > ctx.max_query_graph_nodes {
+            return Err(
+                UserError::QueryTooComplex { nodes, max_nodes: ctx.max_query_graph_nodes }.into()
+            );
+        }
+
+        // the relaxed query graph is moved into the ranking rule graph, only the given one is cloned
+        let query_graph = if self.removed_term_ids.is_empty() {
+            Cow::Borrowed(query_graph)
+        } else {
+            // a node is removed when all its query words are, like by the terms matching strategies
+            let removed_nodes: Vec<_> = query_graph
+                .nodes
+                .iter()
+                .filter_map(|(node_id, node)| match &node.data {
+                    QueryNodeData::Term(t)
+                        if t.term_ids.clone().all(|id| self.removed_term_ids.contains(&id)) =>
+                    {
+                        Some(node_id)
+                    }
+                    _ => None,
+                })
+                .collect();
+            let mut relaxed = query_graph.clone();
+            relaxed.remove_nodes_keep_edges(&removed_nodes);
+            self.original_query_graph = Some(query_graph.clone());
+            Cow::Owned(relaxed)
+        };
+
+        if self.single_bucket {
+            self.single_bucket_query = Some(query_graph.into_owned());
+            return Ok(());
+        }
+
         // the `next_max_cost` is the successor integer to the maximum cost of the paths in the graph.
         //
         // When there is a matching strategy, it also factors the additional costs of:
         // 1. The words that are matched in phrases
         // 2. Skipping words (by adding them to the paths with a cost)
         let mut next_max_cost = 1;
-        let removal_cost = if let Some(terms_matching_strategy) = self.terms_matching_strategy {
+        let mut collapsed_query_graph = None;
+        let removal_cost = if let Some(relaxation_strategy) = &self.relaxation_strategy {
             // add the cost of the phrase to the next_max_cost
             next_max_cost += query_graph
                 .words_in_phrases_count(ctx)
@@ -150,44 +331,36 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
                 // where only the phrase is matching, and none of the non-phrase words.
                 // With the `1` that `next_max_cost` is initialized with, this gets counted twice.
                 .saturating_sub(1) as u64;
-            match terms_matching_strategy {
-                TermsMatchingStrategy::Last => {
-                    let removal_order =
-                        query_graph.removal_order_for_terms_matching_strategy_last(ctx);
-                    let mut forbidden_nodes =
-                        SmallBitmap::for_interned_values_in(&query_graph.nodes);
-                    let mut costs = query_graph.nodes.map(|_| None);
-                    // FIXME: this works because only words uses termsmatchingstrategy at the moment.
-                    for ns in removal_order {
-                        for n in ns.iter() {
-                            *costs.get_mut(n) = Some((1, forbidden_nodes.clone()));
-                        }
-                        forbidden_nodes.union(&ns);
-                    }
-                    costs
+            let mut removal_order = relaxation_strategy.removal_order(ctx, &query_graph)?;
+            if let Some(max_relaxation_steps) = self.max_relaxation_steps {
+                if removal_order.len() > max_relaxation_steps {
+                    // the remaining removal steps are collapsed into a single last bucket,
+                    // matching the query graph without any of the removable nodes
+                    let mut collapsed = QueryGraph::clone(&query_graph);
+                    let removed_nodes: Vec<_> =
+                        removal_order.iter().flat_map(|ns| ns.iter()).collect();
+                    collapsed.remove_nodes_keep_edges(&removed_nodes);
+                    collapsed_query_graph = Some(collapsed);
+                    removal_order.truncate(max_relaxation_steps);
+                    // the collapsed bucket costs one more than the last removal step
+                    next_max_cost += 1;
                 }
-                TermsMatchingStrategy::Frequency => {
-                    let removal_order =
-                        query_graph.removal_order_for_terms_matching_strategy_frequency(ctx)?;
-                    let mut forbidden_nodes =
-                        SmallBitmap::for_interned_values_in(&query_graph.nodes);
-                    let mut costs = query_graph.nodes.map(|_| None);
-                    // FIXME: this works because only words uses termsmatchingstrategy at the moment.
-                    for ns in removal_order {
-                        for n in ns.iter() {
-                            *costs.get_mut(n) = Some((1, forbidden_nodes.clone()));
-                        }
-                        forbidden_nodes.union(&ns);
-                    }
-                    costs
+            }
+            let mut forbidden_nodes = SmallBitmap::for_interned_values_in(&query_graph.nodes);
+            let mut costs = query_graph.nodes.map(|_| None);
+            // FIXME: this works because only words uses termsmatchingstrategy at the moment.
+            for ns in removal_order {
+                for n in ns.iter() {
+                    *costs.get_mut(n) = Some((1, forbidden_nodes.clone()));
                 }
-                TermsMatchingStrategy::All => query_graph.nodes.map(|_| None),
+                forbidden_nodes.union(&ns);
             }
+            costs
         } else {
             query_graph.nodes.map(|_| None)
         };
 
-        let graph = RankingRuleGraph::build(ctx, query_graph.clone(), removal_cost)?;
+        let graph = RankingRuleGraph::build(ctx, query_graph.into_owned(), removal_cost)?;
         let condition_docids_cache = ConditionDocIdsCache::default();
         let dead_ends_cache = DeadEndsCache::new(&graph.conditions_interner);
 
@@ -204,6 +377,7 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
             all_costs,
             cur_cost: 0,
             next_max_cost,
+            collapsed_query_graph,
         };
 
         self.state = Some(state);
@@ -218,6 +392,10 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
         logger: &mut dyn SearchLogger<QueryGraph>,
         universe: &RoaringBitmap,
     ) -> Result<Option<RankingRuleOutput<QueryGraph>>> {
+        if self.single_bucket {
+            return self.next_single_bucket(ctx, universe);
+        }
+
         // Will crash if `next_bucket` is called before `start_iteration` or after `end_iteration`,
         // should never happen
         let mut state = self.state.take().unwrap();
@@ -226,7 +404,23 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
         // Retrieve the cost of the paths to compute
         let Some(&cost) = all_costs.iter().find(|c| **c >= state.cur_cost) else {
             self.state = None;
-            return Ok(None);
+            let Some(collapsed_query_graph) = state.collapsed_query_graph else {
+                return Ok(None);
+            };
+            // the collapsed bucket comes right after the last removal step, with the lowest rank
+            let rank = 1 + collapsed_query_graph.words_in_phrases_count(ctx).saturating_sub(1);
+            let score =
+                self.score(Rank { rank: rank as u32, max_rank: state.next_max_cost as u32 })?;
+            let candidates = compute_query_graph_docids(ctx, &collapsed_query_graph, universe)?;
+            let original_query_graph =
+                self.original_query_graph.as_ref().unwrap_or(&state.graph.query_graph);
+            let details = G::bucket_details(original_query_graph, &collapsed_query_graph);
+            return Ok(Some(RankingRuleOutput {
+                query: collapsed_query_graph,
+                candidates,
+                score,
+                details,
+            }));
         };
         state.cur_cost = cost + 1;
 
@@ -239,10 +433,11 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
             all_costs,
             cur_cost: _,
             next_max_cost,
+            collapsed_query_graph: _,
         } = &mut state;
 
         let rank = *next_max_cost - cost;
-        let score = G::rank_to_score(Rank { rank: rank as u32, max_rank: *next_max_cost as u32 });
+        let score = self.score(Rank { rank: rank as u32, max_rank: *next_max_cost as u32 })?;
 
         let mut universe = universe.clone();
 
@@ -356,9 +551,12 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
             *all_costs = graph.find_all_costs_to_end();
         }
 
+        let original_query_graph = self.original_query_graph.as_ref().unwrap_or(&graph.query_graph);
+        let details = G::bucket_details(original_query_graph, &next_query_graph);
+
         self.state = Some(state);
 
-        Ok(Some(RankingRuleOutput { query: next_query_graph, candidates: bucket, score }))
+        Ok(Some(RankingRuleOutput { query: next_query_graph, candidates: bucket, score, details }))
     }
 
     #[tracing::instrument(level = "trace", skip_all, target = "search::graph_based")]
@@ -368,6 +566,12 @@ impl<'ctx, G: RankingRuleGraphTrait> RankingRule<'ctx, QueryGraph> for GraphBase
         _logger: &mut dyn SearchLogger<QueryGraph>,
     ) {
         self.state = None;
+        self.single_bucket_query = None;
+        self.original_query_graph = None;
+    }
+
+    fn is_count_preserving(&self) -> bool {
+        G::is_count_preserving()
     }
 }
 
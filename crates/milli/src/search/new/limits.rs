@@ -1,6 +1,11 @@
 /// Maximum number of tokens we consider in a single search.
 pub const MAX_TOKEN_COUNT: usize = 1_000;
 
+/// Default maximum number of nodes in the query graph of the `words` ranking rule, see [`SearchContext::max_query_graph_nodes`].
+///
+/// [`SearchContext::max_query_graph_nodes`]: super::SearchContext::max_query_graph_nodes
+pub const DEFAULT_MAX_QUERY_GRAPH_NODES: usize = 1_000;
+
 /// Maximum number of prefixes that can be derived from a single word.
 pub const MAX_PREFIX_COUNT: usize = 1_000;
 /// Maximum number of words that can be derived from a single word with a distance of one to that word.
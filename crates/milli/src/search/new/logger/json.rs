@@ -0,0 +1,166 @@
+use std::any::Any;
+use std::io::{self, Write};
+
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+
+use crate::search::new::geo_sort::Strategy;
+use crate::search::new::ranking_rules::BoxRankingRule;
+use crate::search::new::{RankingRule, RankingRuleQueryTrait, SearchLogger};
+
+/// An event emitted by the [`JsonSearchLogger`], one per line of its output.
+///
+/// The queries are not serialized, only the cardinalities of the sets of documents are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum JsonSearchEvent {
+    #[serde(rename_all = "camelCase")]
+    InitialUniverse { universe_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    RankingRules { ids: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    StartIteration { ranking_rule_idx: usize, ranking_rule: String, universe_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    NextBucket { ranking_rule_idx: usize, ranking_rule: String, universe_len: u64, bucket_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    SkipBucket { ranking_rule_idx: usize, ranking_rule: String, bucket_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    EndIteration { ranking_rule_idx: usize, ranking_rule: String, universe_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    AddToResults { docids: Vec<u32> },
+    #[serde(rename_all = "camelCase")]
+    DistinctExcluded { representative: u32, excluded_len: u64 },
+    #[serde(rename_all = "camelCase")]
+    GeoSortStrategy { strategy: String, geo_candidates: u64, rtree: bool },
+    #[serde(rename_all = "camelCase")]
+    Warning { message: String },
+}
+
+/// A [`SearchLogger`] writing each event as a line of JSON into the given writer.
+///
+/// Writing stops at the first I/O error, which is then returned by [`JsonSearchLogger::finish`].
+pub struct JsonSearchLogger<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonSearchLogger<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, error: None }
+    }
+
+    /// Flushes the writer and returns it, or the first error that happened while logging.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn log(&mut self, event: JsonSearchEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut self.writer, &event)
+            .map_err(io::Error::from)
+            .and_then(|()| self.writer.write_all(b"\n"));
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+}
+
+impl<W: Write, Q: RankingRuleQueryTrait> SearchLogger<Q> for JsonSearchLogger<W> {
+    fn initial_query(&mut self, _query: &Q) {}
+
+    fn initial_universe(&mut self, universe: &RoaringBitmap) {
+        self.log(JsonSearchEvent::InitialUniverse { universe_len: universe.len() });
+    }
+
+    fn query_for_initial_universe(&mut self, _query: &Q) {}
+
+    fn ranking_rules(&mut self, rr: &[BoxRankingRule<'_, Q>]) {
+        self.log(JsonSearchEvent::RankingRules { ids: rr.iter().map(|rr| rr.id()).collect() });
+    }
+
+    fn start_iteration_ranking_rule(
+        &mut self,
+        ranking_rule_idx: usize,
+        ranking_rule: &dyn RankingRule<'_, Q>,
+        _query: &Q,
+        universe: &RoaringBitmap,
+    ) {
+        self.log(JsonSearchEvent::StartIteration {
+            ranking_rule_idx,
+            ranking_rule: ranking_rule.id(),
+            universe_len: universe.len(),
+        });
+    }
+
+    fn next_bucket_ranking_rule(
+        &mut self,
+        ranking_rule_idx: usize,
+        ranking_rule: &dyn RankingRule<'_, Q>,
+        universe: &RoaringBitmap,
+        bucket: &RoaringBitmap,
+    ) {
+        self.log(JsonSearchEvent::NextBucket {
+            ranking_rule_idx,
+            ranking_rule: ranking_rule.id(),
+            universe_len: universe.len(),
+            bucket_len: bucket.len(),
+        });
+    }
+
+    fn skip_bucket_ranking_rule(
+        &mut self,
+        ranking_rule_idx: usize,
+        ranking_rule: &dyn RankingRule<'_, Q>,
+        bucket: &RoaringBitmap,
+    ) {
+        self.log(JsonSearchEvent::SkipBucket {
+            ranking_rule_idx,
+            ranking_rule: ranking_rule.id(),
+            bucket_len: bucket.len(),
+        });
+    }
+
+    fn end_iteration_ranking_rule(
+        &mut self,
+        ranking_rule_idx: usize,
+        ranking_rule: &dyn RankingRule<'_, Q>,
+        universe: &RoaringBitmap,
+    ) {
+        self.log(JsonSearchEvent::EndIteration {
+            ranking_rule_idx,
+            ranking_rule: ranking_rule.id(),
+            universe_len: universe.len(),
+        });
+    }
+
+    fn add_to_results(&mut self, docids: &[u32]) {
+        self.log(JsonSearchEvent::AddToResults { docids: docids.to_vec() });
+    }
+
+    fn distinct_excluded(&mut self, representative: u32, excluded: &RoaringBitmap) {
+        self.log(JsonSearchEvent::DistinctExcluded {
+            representative,
+            excluded_len: excluded.len(),
+        });
+    }
+
+    fn geo_sort_strategy(&mut self, strategy: Strategy, geo_candidates: u64, rtree: bool) {
+        self.log(JsonSearchEvent::GeoSortStrategy {
+            strategy: format!("{strategy:?}"),
+            geo_candidates,
+            rtree,
+        });
+    }
+
+    fn warning(&mut self, message: &str) {
+        self.log(JsonSearchEvent::Warning { message: message.to_owned() });
+    }
+
+    fn log_internal_state(&mut self, _rr: &dyn Any) {}
+}
@@ -0,0 +1,102 @@
+use std::any::Any;
+
+use roaring::RoaringBitmap;
+
+use crate::search::new::geo_sort::Strategy;
+use crate::search::new::ranking_rules::BoxRankingRule;
+use crate::search::new::{RankingRule, RankingRuleQueryTrait, SearchLogger};
+
+/// The counters accumulated by a [`MetricsLogger`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchMetrics {
+    /// The id of each ranking rule along with the number of buckets it computed
+    pub buckets_per_ranking_rule: Vec<(String, u64)>,
+    /// The total number of documents in the buckets computed by the ranking rules.
+    ///
+    /// A document is counted once for every bucket it belongs to.
+    pub documents_scanned: u64,
+    /// The number of times the geo sort refilled its buffer using the rtree
+    pub geo_rtree_fills: u64,
+    /// The number of points of the rtree visited by the geo sort to refill its buffer
+    pub geo_rtree_points_visited: u64,
+    /// The number of times the geo sort refilled its buffer by iterating over the documents
+    pub geo_iterative_fills: u64,
+    /// The number of documents removed by the distinct rule
+    pub distinct_excluded: u64,
+}
+
+/// A [`SearchLogger`] counting what happens during the search, for monitoring purposes.
+///
+/// The counters are accumulated across searches until the logger is dropped.
+#[derive(Default)]
+pub struct MetricsLogger {
+    metrics: SearchMetrics,
+    /// The position in `buckets_per_ranking_rule` of each ranking rule of the current search
+    ranking_rules: Vec<usize>,
+}
+
+impl MetricsLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the counters accumulated so far.
+    pub fn snapshot(&self) -> SearchMetrics {
+        self.metrics.clone()
+    }
+}
+
+impl<Q: RankingRuleQueryTrait> SearchLogger<Q> for MetricsLogger {
+    fn initial_query(&mut self, _query: &Q) {}
+
+    fn initial_universe(&mut self, _universe: &RoaringBitmap) {}
+
+    fn query_for_initial_universe(&mut self, _query: &Q) {}
+
+    fn ranking_rules(&mut self, rr: &[BoxRankingRule<'_, Q>]) {
+        let buckets = &mut self.metrics.buckets_per_ranking_rule;
+        self.ranking_rules = rr
+            .iter()
+            .map(|rr| {
+                let id = rr.id();
+                buckets.iter().position(|(rr_id, _)| *rr_id == id).unwrap_or_else(|| {
+                    buckets.push((id, 0));
+                    buckets.len() - 1
+                })
+            })
+            .collect();
+    }
+
+    fn next_bucket_ranking_rule(
+        &mut self,
+        ranking_rule_idx: usize,
+        _ranking_rule: &dyn RankingRule<'_, Q>,
+        _universe: &RoaringBitmap,
+        bucket: &RoaringBitmap,
+    ) {
+        if let Some(&position) = self.ranking_rules.get(ranking_rule_idx) {
+            self.metrics.buckets_per_ranking_rule[position].1 += 1;
+        }
+        self.metrics.documents_scanned += bucket.len();
+    }
+
+    fn add_to_results(&mut self, _docids: &[u32]) {}
+
+    fn distinct_excluded(&mut self, _representative: u32, excluded: &RoaringBitmap) {
+        self.metrics.distinct_excluded += excluded.len();
+    }
+
+    fn geo_sort_strategy(&mut self, _strategy: Strategy, _geo_candidates: u64, rtree: bool) {
+        if rtree {
+            self.metrics.geo_rtree_fills += 1;
+        } else {
+            self.metrics.geo_iterative_fills += 1;
+        }
+    }
+
+    fn geo_rtree_points_visited(&mut self, visited: u64) {
+        self.metrics.geo_rtree_points_visited += visited;
+    }
+
+    fn log_internal_state(&mut self, _rr: &dyn Any) {}
+}
@@ -1,3 +1,6 @@
+pub mod json;
+pub mod metrics;
+pub mod progress;
 // #[cfg(test)]
 pub mod visual;
 
@@ -5,8 +8,10 @@ use std::any::Any;
 
 use roaring::RoaringBitmap;
 
+use super::geo_sort::Strategy;
 use super::ranking_rules::BoxRankingRule;
 use super::{RankingRule, RankingRuleQueryTrait};
+use crate::score_details::ScoreDetails;
 
 /// Trait for structure logging the execution of a search query.
 pub trait SearchLogger<Q: RankingRuleQueryTrait> {
@@ -40,6 +45,15 @@ pub trait SearchLogger<Q: RankingRuleQueryTrait> {
         _candidates: &RoaringBitmap,
     ) {
     }
+    /// Logs the scores of a ranking rule bucket, from the first ranking rule to the current one
+    fn bucket_computation(&mut self, _ranking_rule_idx: usize, _scores: &[ScoreDetails]) {}
+    /// Logs the progress of the search before each bucket and once it is done
+    ///
+    /// `computed_buckets` is the number of buckets computed so far by all the ranking rules, and `progress`
+    /// the fraction, between 0 and 1, of the documents to skip and return that were reached.
+    /// The ranking rules don't know in advance how many buckets they will return, so the progress is
+    /// counted in documents. It can stay the same for many buckets, but it never decreases.
+    fn bucket_progress(&mut self, _computed_buckets: u64, _progress: f64) {}
     /// Logs the skipping of a ranking rule bucket
     fn skip_bucket_ranking_rule(
         &mut self,
@@ -59,6 +73,30 @@ pub trait SearchLogger<Q: RankingRuleQueryTrait> {
     /// Logs the addition of document ids to the final results
     fn add_to_results(&mut self, _docids: &[u32]);
 
+    /// Logs the documents removed by the distinct rule in favor of the given representative document
+    fn distinct_excluded(&mut self, _representative: u32, _excluded: &RoaringBitmap) {}
+
+    /// Logs the strategy of the geo sort and the number of geo candidates when it refills its buffer,
+    /// along with whether it actually used the rtree or iterated over the geo candidates
+    fn geo_sort_strategy(&mut self, _strategy: Strategy, _geo_candidates: u64, _rtree: bool) {}
+
+    /// Logs the number of points of the rtree visited by the geo sort when it refills its buffer from the rtree
+    fn geo_rtree_points_visited(&mut self, _visited: u64) {}
+
+    /// Logs an unexpected situation from which the search recovered in a degraded way
+    fn warning(&mut self, _message: &str) {}
+
+    /// Logs a bucket of the given ranking rule that contained `dropped` documents outside of its universe,
+    /// which were removed from the bucket to not corrupt the search.
+    ///
+    /// It points to a bug in the ranking rule. The default reports it as a [warning](SearchLogger::warning).
+    fn warn_out_of_universe(&mut self, ranking_rule: &dyn RankingRule<'_, Q>, dropped: u64) {
+        self.warning(&format!(
+            "the ranking rule {} returned {dropped} documents outside of its universe",
+            ranking_rule.id()
+        ));
+    }
+
     /// Logs an internal state in the search algorithms
     fn log_internal_state(&mut self, _rr: &dyn Any);
 }
@@ -0,0 +1,46 @@
+use std::any::Any;
+
+use roaring::RoaringBitmap;
+
+use crate::search::new::ranking_rules::BoxRankingRule;
+use crate::search::new::{RankingRuleQueryTrait, SearchLogger};
+
+/// A [`SearchLogger`] calling a function with the progress of the search, between 0 and 1,
+/// e.g. to display a progress bar during long searches.
+///
+/// See [`SearchLogger::bucket_progress`] for how the progress is computed.
+pub struct ProgressLogger<F> {
+    on_progress: F,
+    /// The last progress given to the function, which is only called when it changes
+    last_progress: Option<f64>,
+}
+
+impl<F: FnMut(f64)> ProgressLogger<F> {
+    pub fn new(on_progress: F) -> Self {
+        Self { on_progress, last_progress: None }
+    }
+}
+
+impl<Q: RankingRuleQueryTrait, F: FnMut(f64)> SearchLogger<Q> for ProgressLogger<F> {
+    fn initial_query(&mut self, _query: &Q) {}
+
+    fn initial_universe(&mut self, _universe: &RoaringBitmap) {
+        // a new search starts from zero
+        self.last_progress = None;
+    }
+
+    fn query_for_initial_universe(&mut self, _query: &Q) {}
+
+    fn ranking_rules(&mut self, _rr: &[BoxRankingRule<'_, Q>]) {}
+
+    fn bucket_progress(&mut self, _computed_buckets: u64, progress: f64) {
+        if self.last_progress != Some(progress) {
+            self.last_progress = Some(progress);
+            (self.on_progress)(progress);
+        }
+    }
+
+    fn add_to_results(&mut self, _docids: &[u32]) {}
+
+    fn log_internal_state(&mut self, _rr: &dyn Any) {}
+}
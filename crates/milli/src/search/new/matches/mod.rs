@@ -495,6 +495,7 @@ mod tests {
                 &mut ctx,
                 Some(query),
                 crate::TermsMatchingStrategy::default(),
+                None,
                 crate::score_details::ScoringStrategy::Skip,
                 false,
                 universe,
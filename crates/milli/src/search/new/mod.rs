@@ -21,15 +21,24 @@ mod vector_sort;
 #[cfg(test)]
 mod tests;
 
-use std::collections::HashSet;
-
-use bucket_sort::{bucket_sort, BucketSortOutput};
+use std::cell::OnceCell;
+#[cfg(feature = "parallel-prefetch")]
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+#[cfg(feature = "parallel-prefetch")]
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use bucket_sort::{bucket_sort, BucketSortOptions, BucketSortOutput};
 use charabia::{Language, TokenizerBuilder};
 use db_cache::DatabaseCache;
 use exact_attribute::ExactAttribute;
 use graph_based_ranking_rule::{Exactness, Fid, Position, Proximity, Typo};
 use heed::RoTxn;
 use interner::{DedupInterner, Interner};
+pub use logger::json::{JsonSearchEvent, JsonSearchLogger};
+pub use logger::metrics::{MetricsLogger, SearchMetrics};
+pub use logger::progress::ProgressLogger;
 pub use logger::visual::VisualSearchLogger;
 pub use logger::{DefaultSearchLogger, SearchLogger};
 use query_graph::{QueryGraph, QueryNode};
@@ -38,15 +47,17 @@ use query_term::{
 };
 use ranking_rules::{
     BoxRankingRule, PlaceholderQuery, RankingRule, RankingRuleOutput, RankingRuleQueryTrait,
+    RankingRuleStackBuilder,
 };
 use resolve_query_graph::{compute_query_graph_docids, PhraseDocIdsCache};
 use roaring::RoaringBitmap;
+use rstar::RTree;
 use sort::Sort;
 
 use self::distinct::facet_string_values;
 use self::geo_sort::GeoSort;
-pub use self::geo_sort::Strategy as GeoSortStrategy;
-use self::graph_based_ranking_rule::Words;
+pub use self::geo_sort::{DistanceUnit as GeoDistanceUnit, Strategy as GeoSortStrategy};
+use self::graph_based_ranking_rule::{WordRelaxationStrategy, Words};
 use self::interner::Interned;
 use self::vector_sort::VectorSort;
 use crate::constants::RESERVED_GEO_FIELD_NAME;
@@ -56,8 +67,8 @@ use crate::score_details::{ScoreDetails, ScoringStrategy};
 use crate::search::new::distinct::apply_distinct_rule;
 use crate::vector::Embedder;
 use crate::{
-    AscDesc, DocumentId, FieldId, Filter, Index, Member, Result, TermsMatchingStrategy, TimeBudget,
-    UserError, Weight,
+    AscDesc, DocumentId, FieldId, Filter, GeoPoint, Index, Member, Result, TermsMatchingStrategy,
+    TimeBudget, UserError, Weight,
 };
 
 /// A structure used throughout the execution of a search query.
@@ -71,6 +82,14 @@ pub struct SearchContext<'ctx> {
     pub phrase_docids: PhraseDocIdsCache,
     pub restricted_fids: Option<RestrictedFids>,
     pub prefix_search: PrefixSearch,
+    /// The maximum number of nodes in the query graph of the `words` ranking rule,
+    /// above which it returns [`UserError::QueryTooComplex`](crate::UserError::QueryTooComplex).
+    pub max_query_graph_nodes: usize,
+    /// The rtree of the index, deserialized at most once per read transaction.
+    pub geo_rtree: OnceCell<Option<Arc<RTree<GeoPoint>>>>,
+    /// The rtree of the index being deserialized on another thread, see [`Self::prefetch_geo_rtree`].
+    #[cfg(feature = "parallel-prefetch")]
+    geo_rtree_prefetch: RefCell<Option<Receiver<bincode::Result<RTree<GeoPoint>>>>>,
 }
 
 impl<'ctx> SearchContext<'ctx> {
@@ -100,9 +119,64 @@ impl<'ctx> SearchContext<'ctx> {
             phrase_docids: <_>::default(),
             restricted_fids: None,
             prefix_search,
+            max_query_graph_nodes: limits::DEFAULT_MAX_QUERY_GRAPH_NODES,
+            geo_rtree: OnceCell::new(),
+            #[cfg(feature = "parallel-prefetch")]
+            geo_rtree_prefetch: RefCell::new(None),
         })
     }
 
+    /// Return the rtree of the index, deserializing it only the first time it is requested.
+    ///
+    /// The cache is bound to the read transaction of the context, so it can never
+    /// return an rtree from a different version of the index.
+    pub fn geo_rtree(&self) -> Result<Option<Arc<RTree<GeoPoint>>>> {
+        if let Some(rtree) = self.geo_rtree.get() {
+            return Ok(rtree.clone());
+        }
+        #[cfg(feature = "parallel-prefetch")]
+        if let Some(receiver) = self.geo_rtree_prefetch.take() {
+            // the result is only missing if the deserialization panicked, it is then done again here
+            if let Ok(rtree) = receiver.recv() {
+                let rtree = Arc::new(rtree.map_err(crate::InternalError::BincodeError)?);
+                return Ok(self.geo_rtree.get_or_init(|| Some(rtree)).clone());
+            }
+        }
+        let rtree = self.index.geo_rtree(self.txn)?.map(Arc::new);
+        Ok(self.geo_rtree.get_or_init(|| rtree).clone())
+    }
+
+    /// Start deserializing the rtree of the index on a thread of the rayon pool, if it isn't already,
+    /// such that [`Self::geo_rtree`] only has to wait for the end of the deserialization.
+    ///
+    /// The read transaction can't be shared between threads: the serialized rtree is copied out of the
+    /// database on the calling thread, and the other thread only owns this copy. It never accesses the
+    /// context, which stays on the calling thread and can be used while the rtree is deserialized.
+    ///
+    /// Nothing is prefetched when called from a thread of the rayon pool, which could wait on itself.
+    #[cfg(feature = "parallel-prefetch")]
+    pub fn prefetch_geo_rtree(&self) -> Result<()> {
+        if rayon::current_thread_index().is_some()
+            || self.geo_rtree.get().is_some()
+            || self.geo_rtree_prefetch.borrow().is_some()
+        {
+            return Ok(());
+        }
+        let Some(bytes) = self.index.geo_rtree_bytes(self.txn)? else {
+            let _ = self.geo_rtree.set(None);
+            return Ok(());
+        };
+
+        let bytes = bytes.to_vec();
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        rayon::spawn(move || {
+            // the search may be over and the receiver dropped
+            let _ = sender.send(bincode::deserialize(&bytes));
+        });
+        *self.geo_rtree_prefetch.borrow_mut() = Some(receiver);
+        Ok(())
+    }
+
     pub fn is_prefix_search_allowed(&self) -> bool {
         self.prefix_search != PrefixSearch::Disabled
     }
@@ -202,19 +276,8 @@ fn resolve_maximally_reduced_query_graph(
 ) -> Result<RoaringBitmap> {
     let mut graph = query_graph.clone();
 
-    let nodes_to_remove = match matching_strategy {
-        TermsMatchingStrategy::Last => query_graph
-            .removal_order_for_terms_matching_strategy_last(ctx)
-            .iter()
-            .flat_map(|x| x.iter())
-            .collect(),
-        TermsMatchingStrategy::Frequency => query_graph
-            .removal_order_for_terms_matching_strategy_frequency(ctx)?
-            .iter()
-            .flat_map(|x| x.iter())
-            .collect(),
-        TermsMatchingStrategy::All => vec![],
-    };
+    let nodes_to_remove: Vec<_> =
+        matching_strategy.removal_order(ctx, query_graph)?.iter().flat_map(|x| x.iter()).collect();
     graph.remove_nodes_keep_edges(&nodes_to_remove);
 
     logger.query_for_initial_universe(&graph);
@@ -279,7 +342,8 @@ fn get_ranking_rules_for_placeholder_search<'ctx>(
     let mut sort = false;
     let mut sorted_fields = HashSet::new();
     let mut geo_sorted = false;
-    let mut ranking_rules: Vec<BoxRankingRule<'ctx, PlaceholderQuery>> = vec![];
+    let mut ranking_rules: RankingRuleStackBuilder<'ctx, PlaceholderQuery> =
+        RankingRuleStackBuilder::new();
     let settings_ranking_rules = ctx.index.criteria(ctx.txn)?;
     for rr in settings_ranking_rules {
         match rr {
@@ -319,7 +383,7 @@ fn get_ranking_rules_for_placeholder_search<'ctx>(
             }
         }
     }
-    Ok(ranking_rules)
+    ranking_rules.build()
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -340,7 +404,8 @@ fn get_ranking_rules_for_vector<'ctx>(
     let mut geo_sorted = false;
 
     let mut vector = false;
-    let mut ranking_rules: Vec<BoxRankingRule<'ctx, PlaceholderQuery>> = vec![];
+    let mut ranking_rules: RankingRuleStackBuilder<'ctx, PlaceholderQuery> =
+        RankingRuleStackBuilder::new();
 
     let settings_ranking_rules = ctx.index.criteria(ctx.txn)?;
     for rr in settings_ranking_rules {
@@ -396,7 +461,19 @@ fn get_ranking_rules_for_vector<'ctx>(
         }
     }
 
-    Ok(ranking_rules)
+    ranking_rules.build()
+}
+
+/// The `words` ranking rule of a query graph search, with the exact boost if there is one.
+fn words_ranking_rule(
+    terms_matching_strategy: TermsMatchingStrategy,
+    exact_boost: Option<f64>,
+) -> Result<Words> {
+    let words = Words::new(Box::new(terms_matching_strategy), None, BTreeSet::new());
+    match exact_boost {
+        Some(exact_boost) => words.with_exact_boost(exact_boost),
+        None => Ok(words),
+    }
 }
 
 /// Return the list of initialised ranking rules to be used for a query graph search.
@@ -405,6 +482,7 @@ fn get_ranking_rules_for_query_graph_search<'ctx>(
     sort_criteria: &Option<Vec<AscDesc>>,
     geo_strategy: geo_sort::Strategy,
     terms_matching_strategy: TermsMatchingStrategy,
+    exact_boost: Option<f64>,
 ) -> Result<Vec<BoxRankingRule<'ctx, QueryGraph>>> {
     // query graph search
     let mut words = false;
@@ -421,7 +499,8 @@ fn get_ranking_rules_for_query_graph_search<'ctx>(
         words = true;
     }
 
-    let mut ranking_rules: Vec<BoxRankingRule<'ctx, QueryGraph>> = vec![];
+    let mut ranking_rules: RankingRuleStackBuilder<'ctx, QueryGraph> =
+        RankingRuleStackBuilder::new();
     let settings_ranking_rules = ctx.index.criteria(ctx.txn)?;
     for rr in settings_ranking_rules {
         // Add Words before any of: typo, proximity, attribute
@@ -431,7 +510,8 @@ fn get_ranking_rules_for_query_graph_search<'ctx>(
             | crate::Criterion::Proximity
             | crate::Criterion::Exactness => {
                 if !words {
-                    ranking_rules.push(Box::new(Words::new(terms_matching_strategy)));
+                    ranking_rules
+                        .push(Box::new(words_ranking_rule(terms_matching_strategy, exact_boost)?));
                     words = true;
                 }
             }
@@ -442,7 +522,8 @@ fn get_ranking_rules_for_query_graph_search<'ctx>(
                 if words {
                     continue;
                 }
-                ranking_rules.push(Box::new(Words::new(terms_matching_strategy)));
+                ranking_rules
+                    .push(Box::new(words_ranking_rule(terms_matching_strategy, exact_boost)?));
                 words = true;
             }
             crate::Criterion::Typo => {
@@ -505,19 +586,18 @@ fn get_ranking_rules_for_query_graph_search<'ctx>(
             }
         }
     }
-    Ok(ranking_rules)
+    ranking_rules.build()
 }
 
 fn resolve_sort_criteria<'ctx, Query: RankingRuleQueryTrait>(
     sort_criteria: &Option<Vec<AscDesc>>,
     ctx: &SearchContext<'ctx>,
-    ranking_rules: &mut Vec<BoxRankingRule<'ctx, Query>>,
+    ranking_rules: &mut RankingRuleStackBuilder<'ctx, Query>,
     sorted_fields: &mut HashSet<String>,
     geo_sorted: &mut bool,
     geo_strategy: geo_sort::Strategy,
 ) -> Result<()> {
     let sort_criteria = sort_criteria.clone().unwrap_or_default();
-    ranking_rules.reserve(sort_criteria.len());
     for criterion in sort_criteria {
         match criterion {
             AscDesc::Asc(Member::Field(field_name)) => {
@@ -544,6 +624,8 @@ fn resolve_sort_criteria<'ctx, Query: RankingRuleQueryTrait>(
                     geo_faceted_docids,
                     point,
                     true,
+                    None,
+                    None,
                 )?));
             }
             AscDesc::Desc(Member::Geo(point)) => {
@@ -556,6 +638,8 @@ fn resolve_sort_criteria<'ctx, Query: RankingRuleQueryTrait>(
                     geo_faceted_docids,
                     point,
                     false,
+                    None,
+                    None,
                 )?));
             }
         };
@@ -597,7 +681,7 @@ pub fn execute_vector_search(
 
     // FIXME: input universe = universe & documents_with_vectors
     // for now if we're computing embeddings for ALL documents, we can assume that this is just universe
-    let ranking_rules = get_ranking_rules_for_vector(
+    let mut ranking_rules = get_ranking_rules_for_vector(
         ctx,
         sort_criteria,
         geo_strategy,
@@ -612,18 +696,21 @@ pub fn execute_vector_search(
     let placeholder_search_logger: &mut dyn SearchLogger<PlaceholderQuery> =
         &mut placeholder_search_logger;
 
-    let BucketSortOutput { docids, scores, all_candidates, degraded } = bucket_sort(
+    let BucketSortOutput { docids, scores, all_candidates, degraded, .. } = bucket_sort(
         ctx,
-        ranking_rules,
+        &mut ranking_rules,
         &PlaceholderQuery,
-        distinct.as_deref(),
         &universe,
         from,
         length,
-        scoring_strategy,
         placeholder_search_logger,
-        time_budget,
-        ranking_score_threshold,
+        BucketSortOptions {
+            distinct: distinct.as_deref().as_slice(),
+            scoring_strategy,
+            time_budget,
+            ranking_score_threshold,
+            ..Default::default()
+        },
     )?;
 
     Ok(PartialSearchResult {
@@ -642,6 +729,7 @@ pub fn execute_search(
     ctx: &mut SearchContext<'_>,
     query: Option<&str>,
     terms_matching_strategy: TermsMatchingStrategy,
+    exact_boost: Option<f64>,
     scoring_strategy: ScoringStrategy,
     exhaustive_number_hits: bool,
     mut universe: RoaringBitmap,
@@ -758,11 +846,12 @@ pub fn execute_search(
         let (graph, new_located_query_terms) = QueryGraph::from_query(ctx, &query_terms)?;
         located_query_terms = Some(new_located_query_terms);
 
-        let ranking_rules = get_ranking_rules_for_query_graph_search(
+        let mut ranking_rules = get_ranking_rules_for_query_graph_search(
             ctx,
             sort_criteria,
             geo_strategy,
             terms_matching_strategy,
+            exact_boost,
         )?;
 
         universe &=
@@ -770,36 +859,44 @@ pub fn execute_search(
 
         bucket_sort(
             ctx,
-            ranking_rules,
+            &mut ranking_rules,
             &graph,
-            distinct.as_deref(),
             &universe,
             from,
             length,
-            scoring_strategy,
             query_graph_logger,
-            time_budget,
-            ranking_score_threshold,
+            BucketSortOptions {
+                distinct: distinct.as_deref().as_slice(),
+                exhaustive_distinct_candidates: exhaustive_number_hits,
+                scoring_strategy,
+                time_budget,
+                ranking_score_threshold,
+                ..Default::default()
+            },
         )?
     } else {
-        let ranking_rules =
+        let mut ranking_rules =
             get_ranking_rules_for_placeholder_search(ctx, sort_criteria, geo_strategy)?;
         bucket_sort(
             ctx,
-            ranking_rules,
+            &mut ranking_rules,
             &PlaceholderQuery,
-            distinct.as_deref(),
             &universe,
             from,
             length,
-            scoring_strategy,
             placeholder_search_logger,
-            time_budget,
-            ranking_score_threshold,
+            BucketSortOptions {
+                distinct: distinct.as_deref().as_slice(),
+                exhaustive_distinct_candidates: exhaustive_number_hits,
+                scoring_strategy,
+                time_budget,
+                ranking_score_threshold,
+                ..Default::default()
+            },
         )?
     };
 
-    let BucketSortOutput { docids, scores, mut all_candidates, degraded } = bucket_sort_output;
+    let BucketSortOutput { docids, scores, mut all_candidates, degraded, .. } = bucket_sort_output;
     let fields_ids_map = ctx.index.fields_ids_map(ctx.txn)?;
 
     // The candidates is the universe unless the exhaustive number of hits
@@ -812,7 +909,15 @@ pub fn execute_search(
 
         if let Some(f) = distinct_field {
             if let Some(distinct_fid) = fields_ids_map.id(f) {
-                all_candidates = apply_distinct_rule(ctx, distinct_fid, &all_candidates)?.remaining;
+                all_candidates = apply_distinct_rule(
+                    ctx,
+                    placeholder_search_logger,
+                    &[distinct_fid],
+                    None,
+                    &all_candidates,
+                    &all_candidates,
+                )?
+                .remaining;
             }
         }
     }
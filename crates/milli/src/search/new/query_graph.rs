@@ -1,6 +1,7 @@
 use std::cmp::{Ordering, Reverse};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
 
 use fxhash::{FxHashMap, FxHasher};
 use roaring::RoaringBitmap;
@@ -321,16 +322,13 @@ impl QueryGraph {
                 })
                 .collect()
         };
-        term_with_frequency.sort_by_key(|(_, frequency)| Reverse(*frequency));
-        let mut term_weight = BTreeMap::new();
-        let mut weight: u16 = 1;
-        let mut peekable = term_with_frequency.into_iter().peekable();
-        while let Some((idx, frequency)) = peekable.next() {
-            term_weight.insert(idx, weight);
-            if peekable.peek().map_or(false, |(_, f)| frequency != *f) {
-                weight += 1;
-            }
-        }
+        // most frequent terms are removed first, ties are broken by removing the last term first
+        term_with_frequency.sort_by_key(|(idx, frequency)| (Reverse(*frequency), Reverse(*idx)));
+        let term_weight: BTreeMap<u8, u16> = term_with_frequency
+            .into_iter()
+            .enumerate()
+            .map(|(weight, (idx, _))| (idx, weight as u16 + 1))
+            .collect();
         let cost_of_term_idx = move |term_idx: u8| *term_weight.get(&term_idx).unwrap();
         Ok(self.removal_order_for_terms_matching_strategy(ctx, cost_of_term_idx))
     }
@@ -339,35 +337,67 @@ impl QueryGraph {
         &self,
         ctx: &SearchContext<'_>,
     ) -> Vec<SmallBitmap<QueryNode>> {
-        let (first_term_idx, last_term_idx) = {
-            let mut first_term_idx = u8::MAX;
-            let mut last_term_idx = 0u8;
-            for (_, node) in self.nodes.iter() {
-                match &node.data {
-                    QueryNodeData::Term(t) => {
-                        if *t.term_ids.end() > last_term_idx {
-                            last_term_idx = *t.term_ids.end();
-                        }
-                        if *t.term_ids.start() < first_term_idx {
-                            first_term_idx = *t.term_ids.start();
-                        }
-                    }
-                    QueryNodeData::Deleted | QueryNodeData::Start | QueryNodeData::End => continue,
-                }
-            }
-            (first_term_idx, last_term_idx)
-        };
-        if first_term_idx >= last_term_idx {
+        let Some(term_idxs) = self.term_index_range() else {
             return vec![];
-        }
+        };
+        let cost_of_term_idx = |term_idx: u8| {
+            let rank = 1 + *term_idxs.end() - term_idx;
+            rank as u16
+        };
+        self.removal_order_for_terms_matching_strategy(ctx, cost_of_term_idx)
+    }
 
+    pub fn removal_order_for_terms_matching_strategy_first(
+        &self,
+        ctx: &SearchContext<'_>,
+    ) -> Vec<SmallBitmap<QueryNode>> {
+        let Some(term_idxs) = self.term_index_range() else {
+            return vec![];
+        };
         let cost_of_term_idx = |term_idx: u8| {
-            let rank = 1 + last_term_idx - term_idx;
+            let rank = 1 + term_idx - *term_idxs.start();
             rank as u16
         };
         self.removal_order_for_terms_matching_strategy(ctx, cost_of_term_idx)
     }
 
+    /// The indexes of the first and last query terms of the graph,
+    /// or `None` when there are less than two terms, such that no term can be removed.
+    fn term_index_range(&self) -> Option<RangeInclusive<u8>> {
+        let mut first_term_idx = u8::MAX;
+        let mut last_term_idx = 0u8;
+        for (_, node) in self.nodes.iter() {
+            match &node.data {
+                QueryNodeData::Term(t) => {
+                    if *t.term_ids.end() > last_term_idx {
+                        last_term_idx = *t.term_ids.end();
+                    }
+                    if *t.term_ids.start() < first_term_idx {
+                        first_term_idx = *t.term_ids.start();
+                    }
+                }
+                QueryNodeData::Deleted | QueryNodeData::Start | QueryNodeData::End => continue,
+            }
+        }
+        (first_term_idx < last_term_idx).then_some(first_term_idx..=last_term_idx)
+    }
+
+    /// Same removal order as [`Self::removal_order_for_terms_matching_strategy_last`],
+    /// but stops before fewer than `min_match` query words remain.
+    pub fn removal_order_for_terms_matching_strategy_min_match(
+        &self,
+        ctx: &SearchContext<'_>,
+        min_match: usize,
+    ) -> Vec<SmallBitmap<QueryNode>> {
+        let mut removal_order = self.removal_order_for_terms_matching_strategy_last(ctx);
+        removal_order.truncate(self.words_count().saturating_sub(min_match));
+        removal_order
+    }
+
+    /// The order in which the nodes of the terms are removed, cheapest first.
+    ///
+    /// A phrase is a single node of the graph and is never removed,
+    /// so that it is either fully matched or the document is not returned.
     pub fn removal_order_for_terms_matching_strategy(
         &self,
         ctx: &SearchContext<'_>,
@@ -397,6 +427,23 @@ impl QueryGraph {
         res
     }
 
+    /// Number of distinct query words in this query graph
+    pub fn words_count(&self) -> usize {
+        self.term_ids().len()
+    }
+
+    /// The ids of the distinct query words in this query graph
+    pub fn term_ids(&self) -> BTreeSet<u8> {
+        self.nodes
+            .iter()
+            .filter_map(|(_, node)| match &node.data {
+                QueryNodeData::Term(t) => Some(t.term_ids.clone()),
+                QueryNodeData::Deleted | QueryNodeData::Start | QueryNodeData::End => None,
+            })
+            .flatten()
+            .collect()
+    }
+
     /// Number of words in the phrases in this query graph
     pub(crate) fn words_in_phrases_count(&self, ctx: &SearchContext<'_>) -> usize {
         let mut word_count = 0;
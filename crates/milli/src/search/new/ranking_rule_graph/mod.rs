@@ -39,6 +39,7 @@ pub use words::{WordsCondition, WordsGraph};
 
 use super::interner::{DedupInterner, FixedSizeInterner, Interned, MappedInterner};
 use super::query_term::LocatedQueryTermSubset;
+use super::ranking_rules::RankingRuleDetails;
 use super::small_bitmap::SmallBitmap;
 use super::{QueryGraph, QueryNode, SearchContext};
 use crate::score_details::{Rank, ScoreDetails};
@@ -114,6 +115,21 @@ pub trait RankingRuleGraphTrait: Sized + 'static {
 
     /// Convert the rank of a path to its corresponding score for the ranking rule
     fn rank_to_score(rank: Rank) -> ScoreDetails;
+
+    /// Additional details about a bucket, given the query graph of the ranking rule
+    /// and the query graph that was used to compute the bucket
+    fn bucket_details(
+        _query_graph: &QueryGraph,
+        _bucket_query_graph: &QueryGraph,
+    ) -> Option<RankingRuleDetails> {
+        None
+    }
+
+    /// Whether all the documents of the universe are returned in the buckets of the ranking rule,
+    /// see [`RankingRule::is_count_preserving`](super::ranking_rules::RankingRule::is_count_preserving)
+    fn is_count_preserving() -> bool {
+        true
+    }
 }
 
 /// The graph used by graph-based ranking rules.
@@ -4,8 +4,9 @@ use super::{ComputedCondition, RankingRuleGraphTrait};
 use crate::score_details::{self, Rank, ScoreDetails};
 use crate::search::new::interner::{DedupInterner, Interned};
 use crate::search::new::query_term::LocatedQueryTermSubset;
+use crate::search::new::ranking_rules::RankingRuleDetails;
 use crate::search::new::resolve_query_graph::compute_query_term_subset_docids;
-use crate::search::new::SearchContext;
+use crate::search::new::{QueryGraph, SearchContext};
 use crate::Result;
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -50,4 +51,18 @@ impl RankingRuleGraphTrait for WordsGraph {
     fn rank_to_score(rank: Rank) -> ScoreDetails {
         ScoreDetails::Words(score_details::Words::from_rank(rank))
     }
+
+    fn bucket_details(
+        query_graph: &QueryGraph,
+        bucket_query_graph: &QueryGraph,
+    ) -> Option<RankingRuleDetails> {
+        let matched_term_ids = bucket_query_graph.term_ids();
+        let dropped_terms = query_graph.term_ids().difference(&matched_term_ids).copied().collect();
+        Some(RankingRuleDetails::Words { matched_words: matched_term_ids.len(), dropped_terms })
+    }
+
+    fn is_count_preserving() -> bool {
+        // the documents that don't match enough words of the query are not returned
+        false
+    }
 }
@@ -50,6 +50,33 @@ pub trait RankingRule<'ctx, Query: RankingRuleQueryTrait> {
         universe: &RoaringBitmap,
     ) -> Result<Option<RankingRuleOutput<Query>>>;
 
+    /// Return up to `n` of the next buckets of this ranking rule at once, in order.
+    ///
+    /// Each bucket is computed on the universe without the documents of the previous buckets of the batch.
+    /// Returning fewer than `n` buckets means that the ranking rule has no more buckets after them,
+    /// like returning `None` from [`next_bucket`](RankingRule::next_bucket).
+    ///
+    /// It is only called by the bucket sort when this ranking rule is the last one, whose buckets are not
+    /// sorted any further. The default calls [`next_bucket`](RankingRule::next_bucket) up to `n` times.
+    fn next_buckets_batch(
+        &mut self,
+        ctx: &mut SearchContext<'ctx>,
+        logger: &mut dyn SearchLogger<Query>,
+        universe: &RoaringBitmap,
+        n: usize,
+    ) -> Result<Vec<RankingRuleOutput<Query>>> {
+        let mut universe = universe.clone();
+        let mut buckets = Vec::new();
+        while buckets.len() < n && !universe.is_empty() {
+            let Some(bucket) = self.next_bucket(ctx, logger, &universe)? else {
+                break;
+            };
+            universe -= &bucket.candidates;
+            buckets.push(bucket);
+        }
+        Ok(buckets)
+    }
+
     /// Finish iterating over the buckets, which yields control to the parent ranking rule
     /// The next call to this ranking rule, if any, will be [`start_iteration`](RankingRule::start_iteration).
     fn end_iteration(
@@ -57,6 +84,134 @@ pub trait RankingRule<'ctx, Query: RankingRuleQueryTrait> {
         ctx: &mut SearchContext<'ctx>,
         logger: &mut dyn SearchLogger<Query>,
     );
+
+    /// Whether the buckets of this ranking rule always cover its whole universe,
+    /// i.e. whether the ranking rule only reorders the documents without filtering any of them.
+    ///
+    /// Among the built-in ranking rules, the sort, geo sort, vector sort and exact attribute
+    /// ranking rules preserve the count, as do the graph-based ones except words.
+    fn is_count_preserving(&self) -> bool {
+        true
+    }
+
+    /// Whether the documents of the universe that the ranking rule didn't return in a bucket when it
+    /// ran out of buckets must be removed from the candidates of the search.
+    ///
+    /// Such a ranking rule is always asked for its buckets, even when its universe contains a single document.
+    fn is_filtering(&self) -> bool {
+        false
+    }
+
+    /// The kind of the ranking rule, constraining its position in a ranking rules stack,
+    /// see [`RankingRuleStackBuilder`].
+    ///
+    /// The default is [`RankingRuleKind::Filter`] for the [filtering](RankingRule::is_filtering) ranking rules,
+    /// and [`RankingRuleKind::Sort`] for the other ones.
+    fn kind(&self) -> RankingRuleKind {
+        if self.is_filtering() {
+            RankingRuleKind::Filter
+        } else {
+            RankingRuleKind::Sort
+        }
+    }
+
+    /// The size below which a bucket of the parent ranking rule is returned as is, without starting
+    /// an iteration of this ranking rule to sort it, when the scores of the documents are not computed.
+    ///
+    /// The documents of such a bucket keep the order of their ids, so a ranking rule should only raise it
+    /// when its order doesn't matter for a few documents. The default skips the buckets with a single document.
+    fn min_universe_size(&self) -> u64 {
+        2
+    }
+
+    /// Tell the ranking rule that the bucket sort skips the first `skipped` documents of the universe given
+    /// to the next call to [`start_iteration`](RankingRule::start_iteration), such that it can avoid sorting them.
+    ///
+    /// It is only called on the first ranking rule, when the skipped documents can be returned in any order
+    /// without changing the page. The default ignores it.
+    fn skip_hint(&mut self, _skipped: u64) {}
+
+    /// Return the documents of the universe that the ranking rule held back instead of returning them in a bucket,
+    /// e.g. because it deferred a decision about them until it ran out of buckets.
+    ///
+    /// It is called when [`next_bucket`](RankingRule::next_bucket) returns `None`, before `end_iteration`, with
+    /// the documents of the universe that were not returned. The leftovers must be part of it, and are added to
+    /// the results in a last bucket with the lowest score, without being sorted by the next ranking rules.
+    /// The default has no leftovers.
+    fn flush_leftovers(&mut self, _universe: &RoaringBitmap) -> RoaringBitmap {
+        RoaringBitmap::new()
+    }
+
+    /// Called when the bucket sort removes documents from the universe of the current iteration of the
+    /// ranking rule between two buckets, e.g. the duplicates excluded by the distinct rule.
+    ///
+    /// The removed documents are part of the universe given to the previous call to `next_bucket`, or to
+    /// `start_iteration`. The ranking rules caching documents derived from their universe can prune them.
+    /// The default does nothing, the next universe is smaller anyway.
+    fn on_universe_shrunk(&mut self, _removed: &RoaringBitmap) {}
+
+    /// Whether the iteration of the ranking rule starts faster when the data it reads from the search context
+    /// is prepared on another thread beforehand, which the bucket sort does with the `parallel-prefetch` feature
+    /// while the ranking rules before this one compute their buckets.
+    ///
+    /// Only the rtree of the index can be prefetched, see `SearchContext::prefetch_geo_rtree`. The default is `false`.
+    fn is_prefetchable(&self) -> bool {
+        false
+    }
+}
+
+/// The kind of a ranking rule, see [`RankingRule::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankingRuleKind {
+    /// A ranking rule removing documents from the search
+    Filter,
+    /// A ranking rule only ordering the documents
+    Sort,
+}
+
+/// A builder of the ranking rules given to the bucket sort, checking that they are in a valid order.
+///
+/// The filtering ranking rules must come before the sorting ones: a filtering ranking rule applied to
+/// the buckets of a sort would only remove the documents of the buckets that it is asked to sort.
+pub struct RankingRuleStackBuilder<'ctx, Query: RankingRuleQueryTrait> {
+    ranking_rules: Vec<BoxRankingRule<'ctx, Query>>,
+}
+
+impl<'ctx, Query: RankingRuleQueryTrait> RankingRuleStackBuilder<'ctx, Query> {
+    pub fn new() -> Self {
+        Self { ranking_rules: Vec::new() }
+    }
+
+    /// Add a ranking rule after the ones already added.
+    pub fn push(&mut self, ranking_rule: BoxRankingRule<'ctx, Query>) -> &mut Self {
+        self.ranking_rules.push(ranking_rule);
+        self
+    }
+
+    /// Return the ranking rules, or an error if a filtering ranking rule comes after a sorting one.
+    pub fn build(self) -> Result<Vec<BoxRankingRule<'ctx, Query>>> {
+        let mut sort_rule = None;
+        for ranking_rule in &self.ranking_rules {
+            match (ranking_rule.kind(), sort_rule) {
+                (RankingRuleKind::Sort, None) => sort_rule = Some(ranking_rule),
+                (RankingRuleKind::Filter, Some(sort_rule)) => {
+                    return Err(crate::InternalError::FilteringRankingRuleAfterSort {
+                        rule_id: ranking_rule.id(),
+                        sort_rule_id: sort_rule.id(),
+                    }
+                    .into());
+                }
+                _ => (),
+            }
+        }
+        Ok(self.ranking_rules)
+    }
+}
+
+impl<'ctx, Query: RankingRuleQueryTrait> Default for RankingRuleStackBuilder<'ctx, Query> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Output of a ranking rule, consisting of the query to be used
@@ -69,4 +224,38 @@ pub struct RankingRuleOutput<Q> {
     pub candidates: RoaringBitmap,
     /// The score for the candidates of the current bucket
     pub score: ScoreDetails,
+    /// Additional information about the bucket that is not part of its score
+    pub details: Option<RankingRuleDetails>,
+}
+
+impl<Q> RankingRuleOutput<Q> {
+    /// A bucket containing the single given document.
+    pub fn single(query: Q, docid: u32, score: ScoreDetails) -> Self {
+        Self { query, candidates: RoaringBitmap::from_iter([docid]), score, details: None }
+    }
+
+    /// A bucket containing the whole universe, e.g. the last bucket of the documents that a sorting
+    /// ranking rule can't sort.
+    pub fn whole(query: Q, universe: &RoaringBitmap, score: ScoreDetails) -> Self {
+        Self { query, candidates: universe.clone(), score, details: None }
+    }
+}
+
+/// Additional information that a ranking rule can attach to one of its buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RankingRuleDetails {
+    /// The distance between the point of the geo sort and the document of the bucket, as computed to sort it
+    GeoDistance {
+        /// The distance in meters between the two lat-lng points on the earth
+        distance: f64,
+    },
+    /// The query words that the documents of the bucket contain, and the ones they don't
+    Words {
+        /// The number of query words that the documents of the bucket contain
+        matched_words: usize,
+        /// The ids of the query words removed by the terms matching strategy, in the order of the query
+        ///
+        /// The id of a word is its position in the query, phrases counting as a single word.
+        dropped_terms: Vec<u8>,
+    },
 }
@@ -177,6 +177,7 @@ impl<'ctx, Query: RankingRuleQueryTrait> RankingRule<'ctx, Query> for Sort<'ctx,
                                 redacted: must_redact,
                                 value,
                             }),
+                            details: None,
                         })
                     },
                 )))
@@ -201,16 +202,13 @@ impl<'ctx, Query: RankingRuleQueryTrait> RankingRule<'ctx, Query> for Sort<'ctx,
             Ok(Some(bucket))
         } else {
             let query = self.original_query.as_ref().unwrap().clone();
-            Ok(Some(RankingRuleOutput {
-                query,
-                candidates: universe.clone(),
-                score: ScoreDetails::Sort(score_details::Sort {
-                    field_name: self.field_name.clone(),
-                    ascending: self.is_ascending,
-                    redacted: self.must_redact,
-                    value: serde_json::Value::Null,
-                }),
-            }))
+            let score = ScoreDetails::Sort(score_details::Sort {
+                field_name: self.field_name.clone(),
+                ascending: self.is_ascending,
+                redacted: self.must_redact,
+                value: serde_json::Value::Null,
+            });
+            Ok(Some(RankingRuleOutput::whole(query, universe, score)))
         }
     }
 
@@ -2,8 +2,9 @@
 //! 1. A basic test works and mark the search as degraded
 //! 2. A test that ensure the filters are affectively applied even with a cutoff of 0
 //! 3. A test that ensure the cutoff works well with the ranking scores
+//! 4. A test that ensure a deadline that already passed degrades the search
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use big_s::S;
 use maplit::hashset;
@@ -67,6 +68,22 @@ fn basic_degraded_search() {
     assert!(result.degraded);
 }
 
+#[test]
+fn deadline_degraded_search() {
+    let index = create_index();
+    let rtxn = index.read_txn().unwrap();
+
+    let mut search = Search::new(&rtxn, &index);
+    search.query("hello puppy kefir");
+    search.limit(3);
+    search.time_budget(TimeBudget::until(Instant::now()));
+
+    let result = search.execute().unwrap();
+    assert!(result.degraded);
+    // the candidates still contain all the documents that were seen before the deadline
+    snapshot!(format!("{:?}", result.candidates), @"RoaringBitmap<[0, 1, 2, 3, 4]>");
+}
+
 #[test]
 fn degraded_search_cannot_skip_filter() {
     let index = create_index();
@@ -5,21 +5,39 @@ interaction with other ranking rules.
 1. no duplicate distinct attributes are ever returned
 2. only the best document (according to the search rules) for each distinct value appears in the result
 3. if a document does not have a distinct attribute, then the distinct rule does not apply to it
+4. the documents excluded by the distinct rule or by the ranking score threshold don't count toward the limit
+5. with multiple distinct attributes, only one document is returned for each combination of their values
+6. without ranking rules, the candidates are only deduplicated beyond the page when asked to
+7. the maximum number of candidates doesn't change the returned documents
+8. the returned documents are always part of the candidates, even when they are truncated
+9. a search returning no document counts one document per distinct value, and doesn't drop the documents
+below the ranking score threshold without their scores
 
 It doesn't test properly:
 - combination of distinct + exhaustive_nbr_hits (because we know it's incorrect)
 - distinct attributes with arrays (because we know it's incorrect as well)
 */
 
+use std::any::Any;
 use std::collections::HashSet;
 
 use big_s::S;
 use heed::RoTxn;
 use maplit::hashset;
+use roaring::RoaringBitmap;
 
 use super::collect_field_values;
 use crate::index::tests::TempIndex;
-use crate::{AscDesc, Criterion, Index, Member, Search, SearchResult, TermsMatchingStrategy};
+use crate::score_details::{ScoreDetails, ScoringStrategy};
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions};
+use crate::search::new::ranking_rules::{BoxRankingRule, PlaceholderQuery};
+use crate::search::new::{
+    get_ranking_rules_for_placeholder_search, DefaultSearchLogger, SearchLogger,
+};
+use crate::{
+    AscDesc, Criterion, GeoSortStrategy, Index, Member, Search, SearchContext, SearchResult,
+    TermsMatchingStrategy,
+};
 
 fn create_index() -> TempIndex {
     let index = TempIndex::new();
@@ -632,3 +650,372 @@ fn test_distinct_typo() {
     ]
     "###);
 }
+
+#[test]
+fn test_distinct_ranking_score_threshold() {
+    let index = create_index();
+    index
+        .update_settings(|s| {
+            s.set_criteria(vec![Criterion::Words, Criterion::Typo]);
+        })
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&txn, &index);
+    s.query("the quick brown fox jumps over the lazy dog");
+    s.scoring_strategy(ScoringStrategy::Detailed);
+    s.limit(100);
+    let SearchResult { documents_ids, document_scores, .. } = s.execute().unwrap();
+
+    let threshold = 0.8;
+    let expected: Vec<_> = documents_ids
+        .iter()
+        .zip(&document_scores)
+        .filter(|(_, scores)| ScoreDetails::global_score(scores.iter()) >= threshold)
+        .map(|(docid, _)| *docid)
+        .take(3)
+        .collect();
+    assert!(!expected.is_empty());
+
+    s.ranking_score_threshold(threshold);
+    s.limit(3);
+    let SearchResult { documents_ids, document_scores, .. } = s.execute().unwrap();
+
+    // the documents below the threshold are skipped without stopping the search early
+    assert_eq!(documents_ids, expected);
+    assert!(document_scores
+        .iter()
+        .all(|scores| ScoreDetails::global_score(scores.iter()) >= threshold));
+    verify_distinct(&index, &txn, None, &documents_ids);
+}
+
+#[test]
+fn test_distinct_count_only() {
+    let index = create_index();
+    index
+        .update_settings(|s| {
+            s.set_criteria(vec![Criterion::Words, Criterion::Typo]);
+        })
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&txn, &index);
+    s.query("the quick brown fox jumps over the lazy dog");
+    s.terms_matching_strategy(TermsMatchingStrategy::Last);
+    s.limit(100);
+    let SearchResult { documents_ids: expected, .. } = s.execute().unwrap();
+
+    s.limit(0);
+    let SearchResult { documents_ids, candidates, .. } = s.execute().unwrap();
+    assert!(documents_ids.is_empty());
+    // only one document is counted for each distinct value
+    assert_eq!(candidates.len(), expected.len() as u64);
+    verify_distinct(&index, &txn, None, &candidates.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_distinct_count_only_ranking_score_threshold() {
+    let index = create_index();
+    index
+        .update_settings(|s| {
+            s.set_criteria(vec![Criterion::Words, Criterion::Typo]);
+        })
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&txn, &index);
+    s.query("the quick brown fox jumps over the lazy dog");
+    s.ranking_score_threshold(0.8);
+    s.limit(100);
+    let SearchResult { documents_ids: expected, .. } = s.execute().unwrap();
+    assert!(!expected.is_empty());
+
+    s.limit(0);
+    let SearchResult { documents_ids, candidates, .. } = s.execute().unwrap();
+    assert!(documents_ids.is_empty());
+    // the documents above the threshold are all counted, even though the scores are not computed
+    assert!(expected.iter().all(|docid| candidates.contains(*docid)));
+}
+
+fn create_composite_index() -> TempIndex {
+    let index = TempIndex::new();
+
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S("rank"), S("brand"), S("color") });
+            s.set_criteria(vec![Criterion::Sort]);
+        })
+        .unwrap();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, "rank": 0, "brand": "a", "color": "red" },
+            { "id": 1, "rank": 1, "brand": "a", "color": "red" },
+            { "id": 2, "rank": 2, "brand": "a", "color": "blue" },
+            { "id": 3, "rank": 3, "brand": "b", "color": "red" },
+            { "id": 4, "rank": 4, "brand": "a" },
+            { "id": 5, "rank": 5, "brand": "a" },
+            { "id": 6, "rank": 6, "brand": "b", "color": "red" },
+        ]))
+        .unwrap();
+
+    index
+}
+
+/// Run a placeholder search, distinct on the brand and color, and return the ids and distinct counts of the documents
+fn composite_distinct_search(
+    index: &Index,
+    sort: bool,
+    from: usize,
+    length: usize,
+) -> (Vec<String>, Vec<u64>) {
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(index, &txn).unwrap();
+    let mut ranking_rules = if sort {
+        let sort_criteria = Some(vec![AscDesc::Asc(Member::Field(S("rank")))]);
+        get_ranking_rules_for_placeholder_search(&ctx, &sort_criteria, GeoSortStrategy::default())
+            .unwrap()
+    } else {
+        vec![]
+    };
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        from,
+        length,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { distinct: &["brand", "color"], ..Default::default() },
+    )
+    .unwrap();
+    (collect_field_values(index, &txn, "id", &output.docids), output.distinct_counts)
+}
+
+#[test]
+fn test_composite_distinct() {
+    let index = create_composite_index();
+    let search = |sort: bool| composite_distinct_search(&index, sort, 0, 20).0;
+
+    // the documents without a color are unique
+    insta::assert_snapshot!(format!("{:?}", search(false)), @r###"["0", "2", "3", "4", "5"]"###);
+    insta::assert_snapshot!(format!("{:?}", search(true)), @r###"["0", "2", "3", "4", "5"]"###);
+}
+
+#[test]
+fn test_distinct_counts() {
+    let index = create_composite_index();
+
+    for sort in [false, true] {
+        let (ids, counts) = composite_distinct_search(&index, sort, 0, 20);
+        assert_eq!(ids, ["0", "2", "3", "4", "5"]);
+        assert_eq!(counts, [1, 0, 1, 0, 0]);
+
+        // the counts stay aligned with the documents of the page
+        let (ids, counts) = composite_distinct_search(&index, sort, 2, 2);
+        assert_eq!(ids, ["3", "4"]);
+        assert_eq!(counts, [1, 0]);
+    }
+}
+
+#[test]
+fn test_distinct_exhaustive_candidates_no_ranking_rules() {
+    let index = create_composite_index();
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    let mut search = |exhaustive_distinct_candidates: bool| {
+        bucket_sort(
+            &mut ctx,
+            &mut [],
+            &PlaceholderQuery,
+            &universe,
+            0,
+            1,
+            &mut DefaultSearchLogger,
+            BucketSortOptions {
+                distinct: &["brand", "color"],
+                exhaustive_distinct_candidates,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    // only the duplicates of `0` are excluded, the scan stops after the first result
+    let output = search(false);
+    assert_eq!(output.docids, [0]);
+    assert_eq!(output.all_candidates, RoaringBitmap::from_iter([0, 2, 3, 4, 5, 6]));
+
+    // `6` is also excluded in favor of `3`
+    let output = search(true);
+    assert_eq!(output.docids, [0]);
+    assert_eq!(output.all_candidates, RoaringBitmap::from_iter([0, 2, 3, 4, 5]));
+    assert_eq!(output.estimated_total_hits, 5);
+}
+
+fn create_variants_index() -> TempIndex {
+    let index = TempIndex::new();
+
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S("rank"), S("letter") });
+            s.set_distinct_field("letter".to_owned());
+            s.set_criteria(vec![Criterion::Sort]);
+        })
+        .unwrap();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, "rank": 0, "letter": "A" },
+            { "id": 1, "rank": 1, "letter": "A" },
+            { "id": 2, "rank": 2, "letter": "A" },
+            { "id": 3, "rank": 3, "letter": "B" },
+            { "id": 4, "rank": 4, "letter": "B" },
+            { "id": 5, "rank": 5, "letter": "C" },
+        ]))
+        .unwrap();
+
+    index
+}
+
+/// Run a placeholder search, distinct on the letter, and return the ids of the documents
+fn variants_search(
+    index: &Index,
+    sort: bool,
+    is_preferred: Option<&dyn Fn(u32) -> bool>,
+    logger: &mut dyn SearchLogger<PlaceholderQuery>,
+) -> Vec<String> {
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(index, &txn).unwrap();
+    let mut ranking_rules = if sort {
+        let sort_criteria = Some(vec![AscDesc::Asc(Member::Field(S("rank")))]);
+        get_ranking_rules_for_placeholder_search(&ctx, &sort_criteria, GeoSortStrategy::default())
+            .unwrap()
+    } else {
+        vec![]
+    };
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        logger,
+        BucketSortOptions { is_preferred, ..Default::default() },
+    )
+    .unwrap();
+    collect_field_values(index, &txn, "id", &output.docids)
+}
+
+#[test]
+fn test_distinct_max_candidates() {
+    let index = create_variants_index();
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    // the representative of the group of `A` isn't its best ranked document
+    let is_preferred = |docid: u32| docid == 0;
+    let mut search = |max_candidates: Option<u64>| {
+        let sort_criteria = Some(vec![AscDesc::Desc(Member::Field(S("rank")))]);
+        let mut ranking_rules = get_ranking_rules_for_placeholder_search(
+            &ctx,
+            &sort_criteria,
+            GeoSortStrategy::default(),
+        )
+        .unwrap();
+        bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            0,
+            20,
+            &mut DefaultSearchLogger,
+            BucketSortOptions {
+                is_preferred: Some(&is_preferred),
+                max_candidates,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let output = search(None);
+    assert_eq!(output.docids, [5, 4, 0]);
+    assert_eq!(output.all_candidates, RoaringBitmap::from_iter([0, 4, 5]));
+    assert!(!output.candidates_truncated);
+
+    // the representatives are still chosen among all the candidates,
+    // and the returned documents are kept in the candidates
+    let output = search(Some(2));
+    assert_eq!(output.docids, [5, 4, 0]);
+    assert_eq!(output.all_candidates, RoaringBitmap::from_iter([0, 4, 5]));
+    assert!(output.candidates_truncated);
+    assert_eq!(output.estimated_total_hits, 3);
+}
+
+#[test]
+fn test_distinct_preferred() {
+    let index = create_variants_index();
+
+    // the preferred documents win over the best ranked ones
+    let search = |sort: bool, preferred: &[u32]| {
+        let is_preferred = |docid: u32| preferred.contains(&docid);
+        variants_search(&index, sort, Some(&is_preferred), &mut DefaultSearchLogger)
+    };
+
+    for sort in [false, true] {
+        assert_eq!(search(sort, &[]), ["0", "3", "5"]);
+        assert_eq!(search(sort, &[2, 4]), ["2", "4", "5"]);
+        // with two preferred documents in a group, the best ranked one is kept
+        assert_eq!(search(sort, &[2, 1]), ["1", "3", "5"]);
+    }
+}
+
+/// A [`SearchLogger`] recording the documents excluded by the distinct rule
+#[derive(Default)]
+struct DistinctExcludedLogger {
+    excluded: Vec<(u32, Vec<u32>)>,
+}
+
+impl SearchLogger<PlaceholderQuery> for DistinctExcludedLogger {
+    fn initial_query(&mut self, _query: &PlaceholderQuery) {}
+
+    fn initial_universe(&mut self, _universe: &RoaringBitmap) {}
+
+    fn query_for_initial_universe(&mut self, _query: &PlaceholderQuery) {}
+
+    fn ranking_rules(&mut self, _rr: &[BoxRankingRule<'_, PlaceholderQuery>]) {}
+
+    fn add_to_results(&mut self, _docids: &[u32]) {}
+
+    fn distinct_excluded(&mut self, representative: u32, excluded: &RoaringBitmap) {
+        self.excluded.push((representative, excluded.iter().collect()));
+    }
+
+    fn log_internal_state(&mut self, _rr: &dyn Any) {}
+}
+
+#[test]
+fn test_distinct_excluded_logger() {
+    let index = create_variants_index();
+
+    for sort in [false, true] {
+        let mut logger = DistinctExcludedLogger::default();
+        let ids = variants_search(&index, sort, None, &mut logger);
+        assert_eq!(ids, ["0", "3", "5"]);
+        // the group of `5` has no other document, so nothing is logged for it
+        assert_eq!(logger.excluded, [(0, vec![1, 2]), (3, vec![4])]);
+    }
+}
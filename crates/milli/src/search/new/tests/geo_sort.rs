@@ -2,15 +2,25 @@
 This module tests the `geo_sort` ranking rule
 */
 
+use std::cmp::Ordering;
+
 use big_s::S;
 use heed::RoTxn;
 use maplit::hashset;
+use rstar::RTree;
 
 use crate::constants::RESERVED_GEO_FIELD_NAME;
 use crate::index::tests::TempIndex;
-use crate::score_details::ScoreDetails;
+use crate::score_details::{ScoreDetails, ScoringStrategy};
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions, BucketSorter};
+use crate::search::new::geo_sort::GeoSort;
+use crate::search::new::ranking_rules::{BoxRankingRule, PlaceholderQuery};
 use crate::search::new::tests::collect_field_values;
-use crate::{AscDesc, Criterion, GeoSortStrategy, Member, Search, SearchResult};
+use crate::search::new::DefaultSearchLogger;
+use crate::{
+    AscDesc, Criterion, Error, FieldsIdsMap, GeoSortStrategy, InternalError, JsonSearchEvent,
+    JsonSearchLogger, Member, Search, SearchContext, SearchResult, UserError,
+};
 
 fn create_index() -> TempIndex {
     let index = TempIndex::new();
@@ -95,6 +105,65 @@ fn test_geo_sort() {
     insta::assert_snapshot!(format!("{scores:#?}"));
 }
 
+#[test]
+fn test_geo_sort_very_close_points() {
+    let index = create_index();
+
+    // all the documents are less than a meter away from the sort point
+    index
+        .add_documents(documents!([
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0.000005, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 0.000001, "lng": 0 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 0.000003, "lng": 0 } },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&rtxn, &index);
+    s.scoring_strategy(crate::score_details::ScoringStrategy::Detailed);
+
+    s.sort_criteria(vec![AscDesc::Asc(Member::Geo([0., 0.]))]);
+    let (ids, _) = execute_iterative_and_rtree_returns_the_same(&rtxn, &index, &mut s);
+    insta::assert_snapshot!(format!("{ids:?}"), @"[1, 2, 0]");
+
+    s.sort_criteria(vec![AscDesc::Desc(Member::Geo([0., 0.]))]);
+    let (ids, _) = execute_iterative_and_rtree_returns_the_same(&rtxn, &index, &mut s);
+    insta::assert_snapshot!(format!("{ids:?}"), @"[0, 2, 1]");
+}
+
+#[test]
+fn test_geo_sort_equidistant_documents() {
+    let index = create_index();
+
+    // `3`, `1`, and `2` are at the same place, and get the docids 0, 1, and 2
+    index
+        .add_documents(documents!([
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": 2 } },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&rtxn, &index);
+    s.scoring_strategy(crate::score_details::ScoringStrategy::Detailed);
+
+    // the equidistant documents are always returned by ascending docid
+    for _ in 0..3 {
+        s.sort_criteria(vec![AscDesc::Asc(Member::Geo([0., 0.]))]);
+        let (ids, _) = execute_iterative_and_rtree_returns_the_same(&rtxn, &index, &mut s);
+        assert_eq!(ids, [0, 3, 1, 2, 4]);
+
+        s.sort_criteria(vec![AscDesc::Desc(Member::Geo([0., 0.]))]);
+        let (ids, _) = execute_iterative_and_rtree_returns_the_same(&rtxn, &index, &mut s);
+        assert_eq!(ids, [4, 3, 1, 2, 0]);
+    }
+}
+
 #[test]
 fn test_geo_sort_around_the_edge_of_the_flat_earth() {
     let index = create_index();
@@ -308,3 +377,600 @@ fn geo_sort_without_any_geo_faceted_documents() {
     insta::assert_snapshot!(format!("{ids:?}"), @"[0, 2, 3]");
     insta::assert_snapshot!(format!("{scores:#?}"));
 }
+
+#[test]
+fn geo_sort_without_rtree() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 4 },
+        ]))
+        .unwrap();
+
+    // simulate an index whose rtree wasn't built yet
+    let mut wtxn = index.write_txn().unwrap();
+    assert!(index.delete_geo_rtree(&mut wtxn).unwrap());
+    wtxn.commit().unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+
+    let mut s = Search::new(&rtxn, &index);
+    s.sort_criteria(vec![AscDesc::Asc(Member::Geo([0., 0.]))]);
+
+    // the dynamic strategy falls back to the iterative geo sort
+    s.geo_sort_strategy(GeoSortStrategy::Dynamic(0));
+    let SearchResult { documents_ids, .. } = s.execute().unwrap();
+    let ids = collect_field_values(&index, &rtxn, "id", &documents_ids);
+    insta::assert_snapshot!(format!("{ids:?}"), @r###"["0", "1", "2", "3", "4"]"###);
+
+    // but the rtree strategy can't
+    s.geo_sort_strategy(GeoSortStrategy::AlwaysRtree(2));
+    let error = s.execute().unwrap_err();
+    assert!(
+        matches!(error, Error::InternalError(InternalError::MissingGeoRtree)),
+        "unexpected error: {error}"
+    );
+
+    // the fallback is logged
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+    let geo_sort =
+        GeoSort::new(GeoSortStrategy::Dynamic(0), geo_faceted_docids, [0., 0.], true, None, None)
+            .unwrap();
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(geo_sort)];
+    let mut logger = JsonSearchLogger::new(Vec::new());
+    bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut logger,
+        BucketSortOptions::default(),
+    )
+    .unwrap();
+    let output = logger.finish().unwrap();
+    let warnings = output
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice::<JsonSearchEvent>(line).unwrap())
+        .filter(|event| matches!(event, JsonSearchEvent::Warning { .. }))
+        .count();
+    assert!(warnings > 0);
+}
+
+#[test]
+fn geo_sort_with_inconsistent_geo_fields() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 4 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    index.validate_geo_consistency(&rtxn).unwrap();
+    drop(rtxn);
+
+    // an rtree missing some of the geo faceted documents
+    let mut wtxn = index.write_txn().unwrap();
+    index.put_geo_rtree(&mut wtxn, &RTree::new()).unwrap();
+    let error = index.validate_geo_consistency(&wtxn).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            Error::InternalError(InternalError::InconsistentGeoFields { candidates: 4, .. })
+        ),
+        "unexpected error: {error}"
+    );
+    // a missing rtree wasn't built yet
+    index.delete_geo_rtree(&mut wtxn).unwrap();
+    index.validate_geo_consistency(&wtxn).unwrap();
+
+    // simulate a fields ids map rebuilt without the geo fields
+    let mut fields_ids_map = FieldsIdsMap::new();
+    fields_ids_map.insert("id").unwrap();
+    index.put_fields_ids_map(&mut wtxn, &fields_ids_map).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let error = index.validate_geo_consistency(&rtxn).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            Error::InternalError(InternalError::InconsistentGeoFields { candidates: 4, .. })
+        ),
+        "unexpected error: {error}"
+    );
+
+    // the geo sort reports the corruption instead of panicking
+    let mut s = Search::new(&rtxn, &index);
+    s.sort_criteria(vec![AscDesc::Asc(Member::Geo([0., 0.]))]);
+    s.geo_sort_strategy(GeoSortStrategy::AlwaysIterative(2));
+    let error = s.execute().unwrap_err();
+    assert!(
+        matches!(
+            error,
+            Error::InternalError(InternalError::InconsistentGeoFields { candidates: 4, .. })
+        ),
+        "unexpected error: {error}"
+    );
+}
+
+fn geo_sort_ranking_rules<'ctx>(
+    index: &TempIndex,
+    rtxn: &RoTxn<'_>,
+) -> Vec<BoxRankingRule<'ctx, PlaceholderQuery>> {
+    let geo_faceted_docids = index.geo_faceted_documents_ids(rtxn).unwrap();
+    let geo_sort =
+        GeoSort::new(GeoSortStrategy::default(), geo_faceted_docids, [0., 0.], true, None, None)
+            .unwrap();
+    vec![Box::new(geo_sort)]
+}
+
+#[test]
+fn geo_sort_bucket_sorter() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 5, RESERVED_GEO_FIELD_NAME: { "lat": 6, "lng": -5 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 3, "lng": 5 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 6 }, { "id": 8 }, { "id": 7 }, { "id": 10 }, { "id": 9 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+
+    let all = bucket_sort(
+        &mut ctx,
+        &mut geo_sort_ranking_rules(&index, &rtxn),
+        &PlaceholderQuery,
+        &universe,
+        1,
+        9,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+    )
+    .unwrap();
+    assert_eq!(all.docids.len(), 9);
+    // the details are only collected when they are asked for
+    assert!(all.details.is_empty());
+
+    // the pages concatenated are the same as a single bucket sort of their combined length
+    let mut ranking_rules = geo_sort_ranking_rules(&index, &rtxn);
+    let mut logger = DefaultSearchLogger;
+    let mut sorter = BucketSorter::new(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        1,
+        &mut logger,
+        BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+    );
+    let mut docids = vec![];
+    let mut scores = vec![];
+    for length in [2, 3, 4] {
+        let page = sorter.next_page(length).unwrap();
+        assert_eq!(page.docids.len(), length);
+        docids.extend(page.docids);
+        scores.extend(page.scores);
+    }
+    assert_eq!(docids, all.docids);
+    assert_eq!(scores, all.scores);
+
+    // only the last document is left, then the pages are empty
+    let page = sorter.next_page(4).unwrap();
+    assert_eq!(page.docids.len(), 1);
+    let page = sorter.next_page(4).unwrap();
+    assert!(page.docids.is_empty());
+    assert_eq!(page.all_candidates, universe);
+}
+
+#[test]
+fn geo_sort_max_distance() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 5, RESERVED_GEO_FIELD_NAME: { "lat": 6, "lng": -5 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 3, "lng": 5 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 6 }, { "id": 8 }, { "id": 7 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+
+    for strategy in [
+        GeoSortStrategy::AlwaysIterative(2),
+        GeoSortStrategy::AlwaysIterative(1000),
+        GeoSortStrategy::AlwaysRtree(2),
+        GeoSortStrategy::AlwaysRtree(1000),
+    ] {
+        for scoring_strategy in [ScoringStrategy::Skip, ScoringStrategy::Detailed] {
+            for ascending in [true, false] {
+                let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+                // `3` is at about 314km of the point, just beyond the maximum distance
+                let geo_sort = GeoSort::new(
+                    strategy,
+                    geo_faceted_docids.clone(),
+                    [0., 0.],
+                    ascending,
+                    None,
+                    Some(300_000.),
+                )
+                .unwrap();
+                let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+                    vec![Box::new(geo_sort)];
+                let output = bucket_sort(
+                    &mut ctx,
+                    &mut ranking_rules,
+                    &PlaceholderQuery,
+                    &universe,
+                    0,
+                    20,
+                    &mut DefaultSearchLogger,
+                    BucketSortOptions { scoring_strategy, ..Default::default() },
+                )
+                .unwrap();
+
+                let ids = collect_field_values(&index, &rtxn, "id", &output.docids);
+                let expected = if ascending { ["0", "1", "2"] } else { ["2", "1", "0"] };
+                assert_eq!(ids, expected, "{strategy:?}, {scoring_strategy:?}, {ascending}");
+                // the documents too far and without location are removed from the search
+                let mut all_candidates: Vec<_> = output.all_candidates.iter().collect();
+                all_candidates.sort_unstable();
+                let mut docids = output.docids.clone();
+                docids.sort_unstable();
+                assert_eq!(all_candidates, docids);
+                assert_eq!(output.estimated_total_hits, 3);
+            }
+        }
+    }
+}
+
+#[test]
+fn geo_sort_multiple_points() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 1 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 4 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 8.5 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 12 } },
+            { "id": 5, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 6.5 } },
+            { "id": 6 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+
+    for strategy in [
+        GeoSortStrategy::AlwaysIterative(2),
+        GeoSortStrategy::AlwaysIterative(1000),
+        GeoSortStrategy::AlwaysRtree(2),
+        GeoSortStrategy::AlwaysRtree(1000),
+    ] {
+        for ascending in [true, false] {
+            let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+            let geo_sort = GeoSort::with_points(
+                strategy,
+                geo_faceted_docids.clone(),
+                vec![[0., 0.], [0., 10.]],
+                ascending,
+                None,
+                None,
+            )
+            .unwrap();
+            let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(geo_sort)];
+            let output = bucket_sort(
+                &mut ctx,
+                &mut ranking_rules,
+                &PlaceholderQuery,
+                &universe,
+                0,
+                20,
+                &mut DefaultSearchLogger,
+                BucketSortOptions {
+                    scoring_strategy: ScoringStrategy::Detailed,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+            // the documents are sorted by their distance to the nearest point: 0°, 1°, 1.5°, 2°, 3.5° and 4°
+            let ids = collect_field_values(&index, &rtxn, "id", &output.docids);
+            let expected = if ascending {
+                ["0", "1", "3", "4", "5", "2", "6"]
+            } else {
+                ["2", "5", "4", "3", "1", "0", "6"]
+            };
+            assert_eq!(ids, expected, "{strategy:?}, {ascending}");
+
+            // the score of each document is given relatively to its nearest point
+            let targets: Vec<_> = output
+                .scores
+                .iter()
+                .map(|scores| match &scores[..] {
+                    [ScoreDetails::GeoSort(geo_sort)] => geo_sort.target_point,
+                    scores => panic!("unexpected scores: {scores:?}"),
+                })
+                .collect();
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|id| match *id {
+                    "0" | "1" | "2" | "6" => [0., 0.],
+                    _ => [0., 10.],
+                })
+                .collect();
+            assert_eq!(targets, expected, "{strategy:?}, {ascending}");
+        }
+    }
+
+    // the documents can't be sorted without any point
+    let error = GeoSort::<PlaceholderQuery>::with_points(
+        GeoSortStrategy::default(),
+        geo_faceted_docids,
+        vec![],
+        true,
+        None,
+        None,
+    )
+    .err()
+    .unwrap();
+    assert!(
+        matches!(error, Error::UserError(UserError::InvalidGeoSort { .. })),
+        "unexpected error: {error}"
+    );
+}
+
+/// Compares the scores of two documents sorted by the same geo sort, the best score being the greater
+fn cmp_geo_scores(left: &ScoreDetails, right: &ScoreDetails) -> Option<Ordering> {
+    match (left, right) {
+        (ScoreDetails::GeoSort(left), ScoreDetails::GeoSort(right)) => left.partial_cmp(right),
+        _ => None,
+    }
+}
+
+#[test]
+fn geo_sort_scores_follow_the_sort_direction() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 0 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": 0 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": 3, "lng": 0 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 4, "lng": 0 } },
+            { "id": 5 },
+        ]))
+        .unwrap();
+    // the rtree strategy can't be used without the rtree
+    let mut wtxn = index.write_txn().unwrap();
+    assert!(index.delete_geo_rtree(&mut wtxn).unwrap());
+    wtxn.commit().unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+    let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+
+    let mut sort = |ascending: bool| {
+        let geo_sort = GeoSort::new(
+            GeoSortStrategy::default(),
+            geo_faceted_docids.clone(),
+            [0., 0.],
+            ascending,
+            None,
+            None,
+        )
+        .unwrap();
+        bucket_sort(
+            &mut ctx,
+            &mut [Box::new(geo_sort) as BoxRankingRule<PlaceholderQuery>],
+            &PlaceholderQuery,
+            &universe,
+            0,
+            20,
+            &mut DefaultSearchLogger,
+            BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+        )
+        .unwrap()
+    };
+
+    for ascending in [true, false] {
+        let output = sort(ascending);
+        let expected: &[u32] = if ascending { &[0, 1, 2, 3, 4, 5] } else { &[4, 3, 2, 1, 0, 5] };
+        assert_eq!(output.docids, expected, "ascending: {ascending}");
+
+        // the first documents returned always have the best scores, the farthest ones when descending
+        for scores in output.scores.windows(2) {
+            let order = cmp_geo_scores(&scores[0][0], &scores[1][0]);
+            assert!(
+                matches!(order, Some(Ordering::Greater | Ordering::Equal)),
+                "ascending: {ascending}, {scores:#?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn geo_sort_skipped_page() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 4 },
+            { "id": 5 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+
+    let sort = |from: usize| {
+        let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+        let geo_sort = GeoSort::new(
+            GeoSortStrategy::AlwaysRtree(10),
+            geo_faceted_docids.clone(),
+            [0., 0.],
+            true,
+            None,
+            None,
+        )
+        .unwrap();
+        let output = bucket_sort(
+            &mut ctx,
+            &mut [Box::new(geo_sort) as BoxRankingRule<PlaceholderQuery>],
+            &PlaceholderQuery,
+            &universe,
+            from,
+            20,
+            &mut DefaultSearchLogger,
+            BucketSortOptions::default(),
+        )
+        .unwrap();
+        let ids = collect_field_values(&index, &rtxn, "id", &output.docids);
+        (ids, ctx.geo_rtree.get().is_some())
+    };
+
+    // a page starting in the geo sorted documents needs the rtree
+    assert_eq!(sort(3), (vec![S("3"), S("4"), S("5")], true));
+    // but the rtree isn't even deserialized when all of them are skipped
+    assert_eq!(sort(4), (vec![S("4"), S("5")], false));
+    assert_eq!(sort(5), (vec![S("5")], false));
+    assert_eq!(sort(10), (Vec::<String>::new(), false));
+}
+
+#[test]
+fn geo_sort_strategy_consistency() {
+    let index = create_index();
+
+    // `3`, `1`, and `2` are at the same place, as are `5` and `6`
+    index
+        .add_documents(documents!([
+            { "id": 3, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 4, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": 2 } },
+            { "id": 5, RESERVED_GEO_FIELD_NAME: { "lat": -1, "lng": 9 } },
+            { "id": 6, RESERVED_GEO_FIELD_NAME: { "lat": -1, "lng": 9 } },
+            { "id": 7, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 12 } },
+            { "id": 8 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&rtxn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+    let mut ctx = SearchContext::new(&index, &rtxn).unwrap();
+
+    let bounding_box = Some([[1.5, -1.], [-1.5, 10.]]);
+    for (points, bounding_box, max_distance) in [
+        (vec![[0., 0.]], None, None),
+        (vec![[0., 0.], [0., 10.]], None, None),
+        (vec![[0., 0.]], bounding_box, None),
+        (vec![[0., 0.]], None, Some(200_000.)),
+    ] {
+        for ascending in [true, false] {
+            let mut geo_sort = GeoSort::with_points(
+                GeoSortStrategy::Dynamic(3),
+                geo_faceted_docids.clone(),
+                points.clone(),
+                ascending,
+                bounding_box,
+                max_distance,
+            )
+            .unwrap();
+
+            // with a cache of 3 documents, the cache is refilled in the middle of the equidistant ones
+            let buckets = geo_sort.assert_strategy_consistency(&mut ctx, &universe);
+            let equidistant: Vec<_> = buckets
+                .iter()
+                .filter(|(candidates, _)| candidates.len() == 1)
+                .flat_map(|(candidates, _)| candidates)
+                .filter(|docid| *docid <= 2)
+                .collect();
+            // the equidistant documents are always returned by ascending docid
+            assert_eq!(equidistant, [0, 1, 2], "{points:?}, {bounding_box:?}, {max_distance:?}");
+
+            // the strategy decision only depends on the number of geo candidates
+            assert!(!geo_sort.use_rtree(2));
+            assert!(geo_sort.use_rtree(3));
+        }
+    }
+}
+
+#[test]
+fn geo_sort_prefetch() {
+    let index = create_index();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 2, RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3 },
+        ]))
+        .unwrap();
+
+    let rtxn = index.read_txn().unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&rtxn).unwrap();
+
+    let geo_sort = |strategy| {
+        GeoSort::new(strategy, geo_faceted_docids.clone(), [0., 0.], true, None, None).unwrap()
+    };
+    let prefetchable =
+        |ranking_rule: BoxRankingRule<PlaceholderQuery>| ranking_rule.is_prefetchable();
+
+    // only the geo sorts that may walk the rtree need it to be prefetched
+    assert!(prefetchable(Box::new(geo_sort(GeoSortStrategy::Dynamic(0)))));
+    assert!(prefetchable(Box::new(geo_sort(GeoSortStrategy::AlwaysRtree(2)))));
+    assert!(!prefetchable(Box::new(geo_sort(GeoSortStrategy::AlwaysIterative(2)))));
+
+    #[cfg(feature = "parallel-prefetch")]
+    {
+        let ctx = SearchContext::new(&index, &rtxn).unwrap();
+        ctx.prefetch_geo_rtree().unwrap();
+        // the prefetched rtree is only cached once it is requested
+        assert!(ctx.geo_rtree.get().is_none());
+        let rtree = ctx.geo_rtree().unwrap().unwrap();
+        assert_eq!(rtree.size(), index.geo_rtree(&rtxn).unwrap().unwrap().size());
+    }
+}
@@ -0,0 +1,284 @@
+/*!
+This module tests the search loggers:
+
+1. the events emitted by the `JsonSearchLogger` can be parsed back
+2. the `MetricsLogger` counts the buckets, the geo sort strategies and the distinct exclusions
+3. the geo sort doesn't walk the rtree again for every refill of its cache, and its refills grow up to
+   its maximum cache size
+4. the geo sort logs its strategy and whether it used the rtree for each refill of its cache
+*/
+
+use big_s::S;
+use maplit::hashset;
+
+use crate::constants::RESERVED_GEO_FIELD_NAME;
+use crate::index::tests::TempIndex;
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions};
+use crate::search::new::geo_sort::GeoSort;
+use crate::search::new::get_ranking_rules_for_placeholder_search;
+use crate::search::new::ranking_rules::{BoxRankingRule, PlaceholderQuery};
+use crate::{
+    AscDesc, Criterion, GeoSortStrategy, JsonSearchEvent, JsonSearchLogger, Member, MetricsLogger,
+    SearchContext, SearchMetrics,
+};
+
+fn create_index() -> TempIndex {
+    let index = TempIndex::new();
+
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S("rank") });
+            s.set_criteria(vec![Criterion::Sort]);
+        })
+        .unwrap();
+
+    index
+        .add_documents(documents!([
+            { "id": 0, "rank": 1 },
+            { "id": 1, "rank": 0 },
+            { "id": 2, "rank": 1 },
+            { "id": 3, "rank": 2 },
+        ]))
+        .unwrap();
+
+    index
+}
+
+#[test]
+fn test_json_logger_round_trip() {
+    let index = create_index();
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let sort_criteria = Some(vec![AscDesc::Asc(Member::Field(S("rank")))]);
+    let mut ranking_rules =
+        get_ranking_rules_for_placeholder_search(&ctx, &sort_criteria, GeoSortStrategy::default())
+            .unwrap();
+
+    let mut logger = JsonSearchLogger::new(Vec::new());
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut logger,
+        BucketSortOptions::default(),
+    )
+    .unwrap();
+    let bytes = logger.finish().unwrap();
+
+    let lines: Vec<&str> = std::str::from_utf8(&bytes).unwrap().lines().collect();
+    let events: Vec<JsonSearchEvent> =
+        lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+    for (line, event) in lines.iter().zip(&events) {
+        assert_eq!(*line, serde_json::to_string(event).unwrap());
+    }
+
+    assert_eq!(events[0], JsonSearchEvent::RankingRules { ids: vec![S("rank:asc")] });
+    assert_eq!(events[1], JsonSearchEvent::InitialUniverse { universe_len: 4 });
+    assert_eq!(
+        events[2],
+        JsonSearchEvent::StartIteration {
+            ranking_rule_idx: 0,
+            ranking_rule: S("rank:asc"),
+            universe_len: 4
+        }
+    );
+
+    let bucket_lens: Vec<u64> = events
+        .iter()
+        .filter_map(|event| match event {
+            JsonSearchEvent::NextBucket { bucket_len, .. } => Some(*bucket_len),
+            _ => None,
+        })
+        .collect();
+    // the last document is added to the results without asking the ranking rule for a bucket
+    assert_eq!(bucket_lens, [1, 2]);
+
+    let added: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            JsonSearchEvent::AddToResults { docids } => Some(docids.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    assert_eq!(added, output.docids);
+    assert_eq!(added, [1, 0, 2, 3]);
+}
+
+#[test]
+fn test_metrics_logger() {
+    let index = TempIndex::new();
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S(RESERVED_GEO_FIELD_NAME), S("letter") });
+        })
+        .unwrap();
+    index
+        .add_documents(documents!([
+            { "id": 0, "letter": "A", RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": 0 } },
+            { "id": 1, "letter": "A", RESERVED_GEO_FIELD_NAME: { "lat": 1, "lng": 1 } },
+            { "id": 2, "letter": "B", RESERVED_GEO_FIELD_NAME: { "lat": 2, "lng": -1 } },
+            { "id": 3, "letter": "B", RESERVED_GEO_FIELD_NAME: { "lat": -2, "lng": -2 } },
+            { "id": 4, "letter": "C" },
+        ]))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&txn).unwrap();
+
+    let mut logger = MetricsLogger::new();
+    for (strategy, distinct) in [
+        (GeoSortStrategy::AlwaysIterative(2), &[][..]),
+        (GeoSortStrategy::AlwaysRtree(2), &["letter"][..]),
+    ] {
+        let geo_sort =
+            GeoSort::new(strategy, geo_faceted_docids.clone(), [0., 0.], true, None, None).unwrap();
+        let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(geo_sort)];
+        bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            0,
+            20,
+            &mut logger,
+            BucketSortOptions { distinct, ..Default::default() },
+        )
+        .unwrap();
+    }
+
+    let SearchMetrics {
+        buckets_per_ranking_rule,
+        documents_scanned,
+        geo_rtree_fills,
+        geo_rtree_points_visited: _,
+        geo_iterative_fills,
+        distinct_excluded,
+    } = logger.snapshot();
+    // the first search returns the 4 geo documents one by one, the second one skips the geo
+    // documents removed by the distinct rule, and the last document of each search is added to
+    // the results without asking the ranking rule for a bucket
+    assert_eq!(buckets_per_ranking_rule, [(S("geo_sort"), 6)]);
+    assert_eq!(documents_scanned, 6);
+    assert!(geo_iterative_fills > 0);
+    assert!(geo_rtree_fills > 0);
+    // `1` and `3` are removed in favor of `0` and `2`
+    assert_eq!(distinct_excluded, 2);
+}
+
+#[test]
+fn test_metrics_logger_geo_rtree_refills() {
+    let index = TempIndex::new();
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S(RESERVED_GEO_FIELD_NAME) });
+        })
+        .unwrap();
+    let docs: Vec<_> = (0..16)
+        .map(|id| serde_json::json!({ "id": id, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": id } }))
+        .collect();
+    index.add_documents(documents!(docs)).unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&txn).unwrap();
+
+    // the number of refills of the cache from the rtree and by iterating over the documents,
+    // which visit each point of the rtree once however many they are
+    let mut refills = || {
+        let mut logger = MetricsLogger::new();
+        let geo_sort = GeoSort::new(
+            GeoSortStrategy::AlwaysRtree(2),
+            geo_faceted_docids.clone(),
+            [0., 0.],
+            true,
+            None,
+            None,
+        )
+        .unwrap();
+        let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(geo_sort)];
+        let output = bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            0,
+            20,
+            &mut logger,
+            BucketSortOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(output.docids, (0..16).collect::<Vec<u32>>());
+
+        let SearchMetrics {
+            geo_rtree_fills, geo_rtree_points_visited, geo_iterative_fills, ..
+        } = logger.snapshot();
+        // each refill continues the walk of the rtree where the previous one stopped
+        assert_eq!(geo_rtree_points_visited, 16);
+        (geo_rtree_fills, geo_iterative_fills)
+    };
+
+    // the refills cache 2, 4, 8 and then the 2 remaining documents, instead of 2 documents 8 times
+    assert_eq!(refills(), (4, 0));
+}
+
+#[test]
+fn test_json_logger_geo_sort_strategy() {
+    let index = TempIndex::new();
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_sortable_fields(hashset! { S(RESERVED_GEO_FIELD_NAME) });
+        })
+        .unwrap();
+    let docs: Vec<_> = (0..16)
+        .map(|id| serde_json::json!({ "id": id, RESERVED_GEO_FIELD_NAME: { "lat": 0, "lng": id } }))
+        .collect();
+    index.add_documents(documents!(docs)).unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let geo_faceted_docids = index.geo_faceted_documents_ids(&txn).unwrap();
+
+    let mut logger = JsonSearchLogger::new(Vec::new());
+    let geo_sort =
+        GeoSort::new(GeoSortStrategy::Dynamic(10), geo_faceted_docids, [0., 0.], true, None, None)
+            .unwrap();
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(geo_sort)];
+    bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut logger,
+        BucketSortOptions::default(),
+    )
+    .unwrap();
+    let bytes = logger.finish().unwrap();
+
+    let strategies: Vec<_> = std::str::from_utf8(&bytes)
+        .unwrap()
+        .lines()
+        .filter_map(|line| match serde_json::from_str(line).unwrap() {
+            JsonSearchEvent::GeoSortStrategy { strategy, geo_candidates, rtree } => {
+                Some((strategy, geo_candidates, rtree))
+            }
+            _ => None,
+        })
+        .collect();
+    // the dynamic strategy walks the rtree to cache 10 documents, and iterates over the 6 remaining ones
+    assert_eq!(strategies, [(S("Dynamic(10)"), 16, true), (S("Dynamic(10)"), 6, false)]);
+}
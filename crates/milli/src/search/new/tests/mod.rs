@@ -8,9 +8,11 @@ pub mod integration;
 #[cfg(feature = "all-tokenizations")]
 #[cfg(not(feature = "chinese-pinyin"))]
 pub mod language;
+pub mod logger;
 pub mod ngram_split_words;
 pub mod proximity;
 pub mod proximity_typo;
+pub mod ranking_rules;
 pub mod sort;
 pub mod stop_words;
 pub mod typo;
@@ -0,0 +1,871 @@
+/*!
+This module tests the checks made by `bucket_sort` on the ranking rules:
+
+1. a ranking rule returning more non-empty buckets than there are documents in its universe is reported
+   instead of making the search loop forever
+2. a ranking rule returning empty buckets before the documents of a small universe is allowed
+3. a ranking rule isn't asked to sort a bucket smaller than its minimum universe size when the scores are skipped
+4. the buckets of the last ranking rule are computed in batches
+5. the ranking rules stack builder rejects a filtering ranking rule after a sorting one
+6. the progress of the bucket sort never decreases and reaches 1 once the page is filled
+7. the leftovers of a ranking rule are added after its buckets, and must be part of its universe
+8. a ranking rule is told about the documents removed from its universe by the distinct rule
+9. the documents returned by a ranking rule outside of its universe are dropped with a warning
+*/
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use roaring::RoaringBitmap;
+
+use crate::index::tests::TempIndex;
+use crate::score_details::{ScoreDetails, ScoringStrategy};
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions};
+use crate::search::new::geo_sort::GeoSort;
+use crate::search::new::ranking_rules::{
+    BoxRankingRule, PlaceholderQuery, RankingRule, RankingRuleKind, RankingRuleOutput,
+    RankingRuleStackBuilder,
+};
+use crate::search::new::{DefaultSearchLogger, ProgressLogger, SearchLogger};
+use crate::{
+    Error, GeoSortStrategy, InternalError, JsonSearchEvent, JsonSearchLogger, Result, SearchContext,
+};
+
+/// A faulty ranking rule which never runs out of buckets, always returning the same document
+struct EndlessBuckets;
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for EndlessBuckets {
+    fn id(&self) -> String {
+        "endless".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([0]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+fn search_with_endless_buckets() -> Result<()> {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!([
+            { "id": 0 },
+            { "id": 1 },
+            { "id": 2 },
+        ]))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![Box::new(EndlessBuckets)];
+    bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut DefaultSearchLogger,
+        BucketSortOptions::default(),
+    )?;
+    Ok(())
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "the ranking rule endless returned more than 3 non-empty buckets")]
+fn test_too_many_buckets_panics_in_debug() {
+    let _ = search_with_endless_buckets();
+}
+
+#[test]
+#[cfg(not(debug_assertions))]
+fn test_too_many_buckets_is_an_error_in_release() {
+    let error = search_with_endless_buckets().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "internal: The ranking rule endless returned more non-empty buckets than the 3 documents of its universe."
+    );
+}
+
+/// A ranking rule which returns some empty buckets before returning its whole universe, like the
+/// graph-based ranking rules do for the costs that no document matches
+struct EmptyBucketsFirst {
+    empty_buckets: usize,
+    returned: usize,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for EmptyBucketsFirst {
+    fn id(&self) -> String {
+        "empty_first".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        self.returned = 0;
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        if self.returned > self.empty_buckets {
+            return Ok(None);
+        }
+        let candidates = if self.returned < self.empty_buckets {
+            RoaringBitmap::new()
+        } else {
+            universe.clone()
+        };
+        self.returned += 1;
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates,
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+#[test]
+fn test_empty_buckets_are_allowed() {
+    let index = TempIndex::new();
+    index.add_documents(documents!([{ "id": 0 }])).unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+        vec![Box::new(EmptyBucketsFirst { empty_buckets: 2, returned: 0 })];
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+    )
+    .unwrap();
+    assert_eq!(output.docids, [0]);
+}
+
+/// A ranking rule returning the documents below `2` first
+struct SmallDocidsFirst {
+    id: &'static str,
+    returned: usize,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for SmallDocidsFirst {
+    fn id(&self) -> String {
+        self.id.to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        self.returned = 0;
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        let candidates = match self.returned {
+            0 => universe & RoaringBitmap::from_iter([0, 1]),
+            1 => universe.clone(),
+            _ => return Ok(None),
+        };
+        self.returned += 1;
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates,
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+/// A ranking rule returning the documents one by one, from the largest docid to the smallest,
+/// and only sorting the buckets of at least 3 documents
+struct LargestDocidFirst;
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for LargestDocidFirst {
+    fn id(&self) -> String {
+        "largest_docid_first".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        Ok(universe.max().map(|docid| RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([docid]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+
+    fn min_universe_size(&self) -> u64 {
+        3
+    }
+}
+
+#[test]
+fn test_min_universe_size() {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!([
+            { "id": 0 },
+            { "id": 1 },
+            { "id": 2 },
+            { "id": 3 },
+            { "id": 4 },
+        ]))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let mut search = |scoring_strategy: ScoringStrategy| {
+        let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![
+            Box::new(SmallDocidsFirst { id: "small_first", returned: 0 }),
+            Box::new(LargestDocidFirst),
+        ];
+        bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            0,
+            20,
+            &mut DefaultSearchLogger,
+            BucketSortOptions { scoring_strategy, ..Default::default() },
+        )
+        .unwrap()
+        .docids
+    };
+
+    // the bucket of `0` and `1` is too small to be sorted by the second ranking rule
+    assert_eq!(search(ScoringStrategy::Skip), [0, 1, 4, 3, 2]);
+    // unless the scores must be computed
+    assert_eq!(search(ScoringStrategy::Detailed), [1, 0, 4, 3, 2]);
+}
+
+/// A ranking rule returning the documents one by one, by increasing docid,
+/// and counting the calls to `next_bucket` and `next_buckets_batch`
+struct CountedBuckets {
+    next_bucket_calls: Rc<Cell<usize>>,
+    batch_calls: Rc<Cell<usize>>,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for CountedBuckets {
+    fn id(&self) -> String {
+        "counted_buckets".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        self.next_bucket_calls.set(self.next_bucket_calls.get() + 1);
+        Ok(universe.min().map(|docid| RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([docid]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn next_buckets_batch(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+        n: usize,
+    ) -> Result<Vec<RankingRuleOutput<PlaceholderQuery>>> {
+        self.batch_calls.set(self.batch_calls.get() + 1);
+        Ok(universe
+            .iter()
+            .take(n)
+            .map(|docid| RankingRuleOutput {
+                query: PlaceholderQuery,
+                candidates: RoaringBitmap::from_iter([docid]),
+                score: ScoreDetails::Skipped,
+                details: None,
+            })
+            .collect())
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+#[test]
+fn test_next_buckets_batch() {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!([
+            { "id": 0 },
+            { "id": 1 },
+            { "id": 2 },
+            { "id": 3 },
+            { "id": 4 },
+        ]))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    // the docids, and the number of calls to `next_bucket` and `next_buckets_batch`
+    let mut search = |last: bool| {
+        let next_bucket_calls = Rc::new(Cell::new(0));
+        let batch_calls = Rc::new(Cell::new(0));
+        let counted = Box::new(CountedBuckets {
+            next_bucket_calls: next_bucket_calls.clone(),
+            batch_calls: batch_calls.clone(),
+        });
+        let small_first = Box::new(SmallDocidsFirst { id: "small_first", returned: 0 });
+        let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+            if last { vec![small_first, counted] } else { vec![counted, small_first] };
+        let docids = bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            1,
+            3,
+            &mut DefaultSearchLogger,
+            BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+        )
+        .unwrap()
+        .docids;
+        (docids, next_bucket_calls.get(), batch_calls.get())
+    };
+
+    // a batch for each bucket of the first ranking rule
+    assert_eq!(search(true), (vec![1, 2, 3], 0, 2));
+    // the buckets of a ranking rule that isn't the last one are still computed one by one
+    assert_eq!(search(false), (vec![1, 2, 3], 4, 0));
+}
+
+/// A ranking rule returning random non-empty subsets of its universe
+struct RandomBuckets {
+    rng: StdRng,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for RandomBuckets {
+    fn id(&self) -> String {
+        "random".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        let Some(first) = universe.min() else {
+            return Ok(None);
+        };
+        let candidates =
+            universe.iter().filter(|&docid| docid == first || self.rng.gen_bool(0.5)).collect();
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates,
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+#[test]
+fn test_ranking_rule_stack_builder() {
+    let filter = || {
+        let geo_sort = GeoSort::new(
+            GeoSortStrategy::default(),
+            RoaringBitmap::new(),
+            [0., 0.],
+            true,
+            None,
+            Some(1_000.),
+        )
+        .unwrap();
+        Box::new(geo_sort) as BoxRankingRule<PlaceholderQuery>
+    };
+    let sort = || {
+        Box::new(RandomBuckets { rng: StdRng::seed_from_u64(0) })
+            as BoxRankingRule<PlaceholderQuery>
+    };
+
+    let mut builder = RankingRuleStackBuilder::new();
+    builder.push(filter()).push(sort()).push(sort());
+    let ranking_rules = builder.build().unwrap();
+    let kinds: Vec<_> = ranking_rules.iter().map(|ranking_rule| ranking_rule.kind()).collect();
+    assert_eq!(kinds, [RankingRuleKind::Filter, RankingRuleKind::Sort, RankingRuleKind::Sort]);
+
+    let mut builder = RankingRuleStackBuilder::new();
+    builder.push(sort()).push(filter());
+    let Err(error) = builder.build() else {
+        panic!("the filtering ranking rule after the sort was not reported");
+    };
+    assert!(
+        matches!(
+            &error,
+            Error::InternalError(InternalError::FilteringRankingRuleAfterSort { rule_id, sort_rule_id })
+                if rule_id == "geo_sort" && sort_rule_id == "random"
+        ),
+        "unexpected error: {error}"
+    );
+}
+
+#[test]
+fn test_bucket_sort_progress() {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!(
+            ((0..30).map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>())
+        ))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    let mut progresses = Vec::new();
+    let mut logger = ProgressLogger::new(|progress| progresses.push(progress));
+    for seed in 0..2 {
+        let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> = vec![
+            Box::new(RandomBuckets { rng: StdRng::seed_from_u64(seed) }),
+            Box::new(RandomBuckets { rng: StdRng::seed_from_u64(seed + 1) }),
+        ];
+        let docids = bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            5,
+            10,
+            &mut logger,
+            BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+        )
+        .unwrap()
+        .docids;
+        assert_eq!(docids.len(), 10);
+    }
+    drop(logger);
+
+    // each search starts from 0, and its progress is only reported when it increases
+    let searches: Vec<_> = progresses.split_inclusive(|progress| *progress == 1.).collect();
+    assert_eq!(searches.len(), 2, "{progresses:?}");
+    for progresses in searches {
+        assert_eq!(progresses.first(), Some(&0.), "{progresses:?}");
+        assert!(progresses.windows(2).all(|w| w[0] < w[1]), "{progresses:?}");
+    }
+}
+
+/// A ranking rule returning the even docids one by one, and holding back the odd ones until the end
+struct EvenFirst {
+    /// Whether the leftovers are not part of the universe
+    outside: bool,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for EvenFirst {
+    fn id(&self) -> String {
+        "even_first".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        let Some(even) = universe.iter().find(|docid| docid % 2 == 0) else {
+            return Ok(None);
+        };
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([even]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+
+    fn flush_leftovers(&mut self, universe: &RoaringBitmap) -> RoaringBitmap {
+        if self.outside {
+            RoaringBitmap::from_iter([universe.max().unwrap_or_default() + 1])
+        } else {
+            universe.clone()
+        }
+    }
+}
+
+#[test]
+fn test_flush_leftovers() {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!(
+            ((0..6).map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>())
+        ))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    let mut search = |ranking_rules: &mut [BoxRankingRule<PlaceholderQuery>]| {
+        bucket_sort(
+            &mut ctx,
+            ranking_rules,
+            &PlaceholderQuery,
+            &universe,
+            0,
+            10,
+            &mut DefaultSearchLogger,
+            BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+        )
+    };
+
+    // the leftovers come last, and are not sorted by the next ranking rule
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+        vec![Box::new(EvenFirst { outside: false }), Box::new(LargestDocidFirst)];
+    let output = search(&mut ranking_rules).unwrap();
+    assert_eq!(output.docids, [0, 2, 4, 1, 3, 5]);
+    assert_eq!(output.bucket_boundaries, [0, 1, 2, 3]);
+
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+        vec![Box::new(EvenFirst { outside: true })];
+    let Err(error) = search(&mut ranking_rules) else {
+        panic!("the leftovers outside of the universe were not reported");
+    };
+    assert!(
+        matches!(
+            &error,
+            Error::InternalError(InternalError::RankingRuleCandidatesOutsideUniverse { rule_id })
+                if rule_id == "even_first"
+        ),
+        "unexpected error: {error}"
+    );
+}
+
+/// A ranking rule caching the docids of its universe and returning them one by one,
+/// and reporting an error when a cached docid is not part of the universe anymore
+struct CachedDocids {
+    cache: VecDeque<u32>,
+    removed: Rc<RefCell<RoaringBitmap>>,
+}
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for CachedDocids {
+    fn id(&self) -> String {
+        "cached_docids".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        self.cache = universe.iter().collect();
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        let Some(docid) = self.cache.pop_front() else {
+            return Ok(None);
+        };
+        if !universe.contains(docid) {
+            return Err(
+                InternalError::RankingRuleCandidatesOutsideUniverse { rule_id: self.id() }.into()
+            );
+        }
+        Ok(Some(RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([docid]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+        self.cache.clear();
+    }
+
+    fn on_universe_shrunk(&mut self, removed: &RoaringBitmap) {
+        self.cache.retain(|docid| !removed.contains(*docid));
+        *self.removed.borrow_mut() |= removed;
+    }
+}
+
+#[test]
+fn test_universe_shrunk_by_distinct() {
+    let index = TempIndex::new();
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_distinct_field("group".to_owned());
+        })
+        .unwrap();
+    index
+        .add_documents(documents!(
+            ((0..6).map(|id| serde_json::json!({ "id": id, "group": id / 2 })).collect::<Vec<_>>())
+        ))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let group = index.fields_ids_map(&txn).unwrap().id("group").unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    let removed = Rc::new(RefCell::new(RoaringBitmap::new()));
+    let cached = CachedDocids { cache: VecDeque::new(), removed: removed.clone() };
+    // the buckets of the last ranking rule are computed in batches, the cache must be asked one by one
+    let small_first = SmallDocidsFirst { id: "small_first", returned: 0 };
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+        vec![Box::new(cached), Box::new(small_first)];
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        10,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { distinct: &[group], ..Default::default() },
+    )
+    .unwrap();
+
+    // the duplicates are pruned from the cache before it returns them
+    assert_eq!(output.docids, [0, 2, 4]);
+    assert_eq!(*removed.borrow(), RoaringBitmap::from_iter([1, 3, 5]));
+}
+
+/// A faulty ranking rule returning the documents one by one, by increasing docid,
+/// along with a document that is not part of its universe
+struct StrayDocids;
+
+impl<'ctx> RankingRule<'ctx, PlaceholderQuery> for StrayDocids {
+    fn id(&self) -> String {
+        "stray_docids".to_owned()
+    }
+
+    fn start_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        _universe: &RoaringBitmap,
+        _query: &PlaceholderQuery,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_bucket(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+        universe: &RoaringBitmap,
+    ) -> Result<Option<RankingRuleOutput<PlaceholderQuery>>> {
+        Ok(universe.min().map(|docid| RankingRuleOutput {
+            query: PlaceholderQuery,
+            candidates: RoaringBitmap::from_iter([docid, universe.max().unwrap() + 1]),
+            score: ScoreDetails::Skipped,
+            details: None,
+        }))
+    }
+
+    fn end_iteration(
+        &mut self,
+        _ctx: &mut SearchContext<'ctx>,
+        _logger: &mut dyn SearchLogger<PlaceholderQuery>,
+    ) {
+    }
+}
+
+#[test]
+fn test_out_of_universe_candidates() {
+    let index = TempIndex::new();
+    index
+        .add_documents(documents!(
+            ((0..3).map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>())
+        ))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+
+    // the buckets of the last ranking rule are computed in batches, the stray one must be asked one by one
+    let small_first = SmallDocidsFirst { id: "small_first", returned: 0 };
+    let mut ranking_rules: Vec<BoxRankingRule<PlaceholderQuery>> =
+        vec![Box::new(StrayDocids), Box::new(small_first)];
+    let mut logger = JsonSearchLogger::new(Vec::new());
+    let output = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        0,
+        20,
+        &mut logger,
+        BucketSortOptions::default(),
+    )
+    .unwrap();
+
+    // the stray documents are not returned
+    assert_eq!(output.docids, [0, 1, 2]);
+    let bytes = logger.finish().unwrap();
+    let warnings: Vec<_> = std::str::from_utf8(&bytes)
+        .unwrap()
+        .lines()
+        .filter_map(|line| match serde_json::from_str(line).unwrap() {
+            JsonSearchEvent::Warning { message } => Some(message),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        warnings,
+        vec!["the ranking rule stray_docids returned 1 documents outside of its universe"; 3]
+    );
+}
@@ -9,6 +9,7 @@ This module tests the `sort` ranking rule:
 6. documents with either: (1) no value, (2) null, or (3) an object for the field-to-sort appear at the end of the bucket
 7. boolean values are translated to strings
 8. if a field contains an array, it is sorted by the best value in the array according to the sort rule
+9. the bucket sort reports where each bucket of the first sort rule starts, but not the buckets of the next ones
 */
 
 use big_s::S;
@@ -16,9 +17,14 @@ use maplit::hashset;
 use meili_snap::insta;
 
 use crate::index::tests::TempIndex;
+use crate::score_details::ScoringStrategy;
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions, BucketSortOutput};
+use crate::search::new::ranking_rules::PlaceholderQuery;
 use crate::search::new::tests::collect_field_values;
+use crate::search::new::{get_ranking_rules_for_placeholder_search, DefaultSearchLogger};
 use crate::{
-    score_details, AscDesc, Criterion, Member, Search, SearchResult, TermsMatchingStrategy,
+    score_details, AscDesc, Criterion, GeoSortStrategy, Member, Search, SearchContext,
+    SearchResult, TermsMatchingStrategy,
 };
 
 fn create_index() -> TempIndex {
@@ -354,3 +360,54 @@ fn test_redacted() {
     insta::assert_snapshot!(format!("{documents_ids:?}"), @"[0, 2, 4, 5, 22, 23, 13, 1, 3, 12, 21, 11, 20, 6, 7, 8, 9, 10, 14, 15]");
     insta::assert_json_snapshot!(document_scores_json);
 }
+
+fn sort_by_letter_and_rank(index: &TempIndex, from: usize, length: usize) -> BucketSortOutput {
+    let txn = index.read_txn().unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+    let mut ctx = SearchContext::new(index, &txn).unwrap();
+    let sort_criteria = Some(vec![
+        AscDesc::Asc(Member::Field(S("letter"))),
+        AscDesc::Asc(Member::Field(S("rank"))),
+    ]);
+    let mut ranking_rules =
+        get_ranking_rules_for_placeholder_search(&ctx, &sort_criteria, GeoSortStrategy::default())
+            .unwrap();
+    bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &PlaceholderQuery,
+        &universe,
+        from,
+        length,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_sort_bucket_boundaries() {
+    let index = create_index();
+
+    let BucketSortOutput { docids, scores, bucket_boundaries, .. } =
+        sort_by_letter_and_rank(&index, 0, 100);
+    // one bucket per letter, the buckets of the `rank` sort rule don't create boundaries
+    insta::assert_snapshot!(format!("{docids:?}"), @"[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23]");
+    assert_eq!(bucket_boundaries, [0, 2, 5, 8, 9, 15, 18, 20, 21]);
+
+    // all the documents of a bucket have the same letter score, which differs from the next bucket
+    let mut ends = bucket_boundaries.iter().skip(1).copied().chain([docids.len()]);
+    let mut previous_score = None;
+    for &start in &bucket_boundaries {
+        let end = ends.next().unwrap();
+        let letter_score = &scores[start][0];
+        assert!(scores[start..end].iter().all(|scores| &scores[0] == letter_score));
+        assert_ne!(previous_score, Some(letter_score));
+        previous_score = Some(letter_score);
+    }
+
+    // a page starting in the middle of a bucket still starts with a boundary
+    let BucketSortOutput { docids, bucket_boundaries, .. } = sort_by_letter_and_rank(&index, 3, 4);
+    assert_eq!(docids, [3, 4, 5, 6]);
+    assert_eq!(bucket_boundaries, [0, 2]);
+}
@@ -10,11 +10,41 @@ account by the proximity ranking rule.
 5. Unclosed double quotes still make a phrase
 6. The `all` term matching strategy does not remove any term from the query
 7. The search is capable of returning no results if no documents match the query
+8. The `first` term matching strategy starts removing terms from the query
+starting from the beginning if no more results match it.
+9. The `minMatch` term matching strategy never removes more terms than allowed
+10. The `frequency` term matching strategy removes equally frequent terms from last to first
+11. A phrase is never partially removed by any term matching strategy, even among free words
+12. The `words` ranking rule with the `all` term matching strategy returns a single bucket
+13. The `words` ranking rule reports the query words removed for each document
+14. The removal steps of the `words` ranking rule can be capped, the remaining ones being collapsed
+into a single last bucket
+15. The `words` ranking rule can start from a query graph whose words were already partially removed
+16. The `words` ranking rule returns an error when the query graph has too many nodes
+17. A search without any result to return still counts only the documents matching the query
+18. The `words` ranking rule removes the query words in the order of a custom relaxation strategy
+19. The exact boost widens the gap between the scores of the exact matches and of the first relaxation step
 */
 
+use std::collections::BTreeSet;
+
+use charabia::Tokenizer;
+use heed::RoTxn;
+use roaring::RoaringBitmap;
+
 use crate::index::tests::TempIndex;
+use crate::score_details::{self, ScoreDetails, ScoringStrategy};
+use crate::search::new::bucket_sort::{bucket_sort, BucketSortOptions, BucketSortOutput};
+use crate::search::new::graph_based_ranking_rule::{Proximity, WordRelaxationStrategy, Words};
+use crate::search::new::query_graph::{QueryGraph, QueryNode};
+use crate::search::new::query_term::{located_query_terms_from_tokens, ExtractedTokens};
+use crate::search::new::ranking_rules::BoxRankingRule;
+use crate::search::new::small_bitmap::SmallBitmap;
 use crate::search::new::tests::collect_field_values;
-use crate::{Criterion, Search, SearchResult, TermsMatchingStrategy};
+use crate::search::new::DefaultSearchLogger;
+use crate::{
+    Criterion, Error, Result, Search, SearchContext, SearchResult, TermsMatchingStrategy, UserError,
+};
 
 fn create_index() -> TempIndex {
     let index = TempIndex::new();
@@ -411,6 +441,107 @@ fn test_words_proximity_tms_last_phrase() {
     "###);
 }
 
+#[test]
+fn test_words_tms_first_simple() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let mut s = Search::new(&txn, &index);
+    s.query("quick brown fox jumps");
+    s.terms_matching_strategy(TermsMatchingStrategy::Last);
+    let SearchResult { documents_ids: last_ids, .. } = s.execute().unwrap();
+
+    s.terms_matching_strategy(TermsMatchingStrategy::First);
+    let SearchResult { documents_ids: first_ids, .. } = s.execute().unwrap();
+
+    assert_ne!(first_ids, last_ids);
+
+    // `last` ends up only requiring the first word while `first` ends up only requiring the last word
+    let texts = collect_field_values(&index, &txn, "text", &last_ids);
+    assert!(texts.iter().all(|text| text.contains("quick")), "{texts:#?}");
+    assert!(texts.iter().any(|text| !text.contains("jumps")), "{texts:#?}");
+    let texts = collect_field_values(&index, &txn, "text", &first_ids);
+    assert!(texts.iter().all(|text| text.contains("jumps")), "{texts:#?}");
+}
+
+#[test]
+fn test_words_tms_min_match() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let mut s = Search::new(&txn, &index);
+    s.query("quick brown fox jumps");
+    s.terms_matching_strategy(TermsMatchingStrategy::MinMatch(2));
+    let SearchResult { documents_ids, .. } = s.execute().unwrap();
+
+    // only the last two words can be removed
+    let texts = collect_field_values(&index, &txn, "text", &documents_ids);
+    assert!(
+        texts.iter().all(|text| text.contains("quick") && text.contains("brown")),
+        "{texts:#?}"
+    );
+    assert!(texts.iter().any(|text| !text.contains("fox")), "{texts:#?}");
+
+    // asking for more words than the query contains behaves like `all`
+    s.terms_matching_strategy(TermsMatchingStrategy::MinMatch(10));
+    let SearchResult { documents_ids: min_match_ids, .. } = s.execute().unwrap();
+    s.terms_matching_strategy(TermsMatchingStrategy::All);
+    let SearchResult { documents_ids: all_ids, .. } = s.execute().unwrap();
+    assert_eq!(min_match_ids, all_ids);
+}
+
+#[test]
+fn test_words_tms_phrase_is_atomic() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    for strategy in [
+        TermsMatchingStrategy::Last,
+        TermsMatchingStrategy::First,
+        TermsMatchingStrategy::Frequency,
+        TermsMatchingStrategy::MinMatch(1),
+    ] {
+        let mut s = Search::new(&txn, &index);
+        s.query("the \"quick brown\" fox jumps");
+        s.terms_matching_strategy(strategy);
+        let SearchResult { documents_ids, .. } = s.execute().unwrap();
+
+        // the free words can all be removed, but never a single word of the phrase
+        let texts = collect_field_values(&index, &txn, "text", &documents_ids);
+        assert!(texts.iter().all(|text| text.contains("quick brown")), "{strategy:?}: {texts:#?}");
+        assert!(texts.iter().any(|text| !text.contains("fox")), "{strategy:?}: {texts:#?}");
+    }
+}
+
+#[test]
+fn test_words_tms_frequency_tie() {
+    let index = TempIndex::new();
+    index
+        .update_settings(|s| {
+            s.set_primary_key("id".to_owned());
+            s.set_searchable_fields(vec!["text".to_owned()]);
+            s.set_criteria(vec![Criterion::Words]);
+        })
+        .unwrap();
+    index
+        .add_documents(documents!([
+            { "id": 0, "text": "alpha gamma" },
+            { "id": 1, "text": "beta gamma" },
+            { "id": 2, "text": "alpha" },
+            { "id": 3, "text": "beta" },
+        ]))
+        .unwrap();
+
+    let txn = index.read_txn().unwrap();
+    let mut s = Search::new(&txn, &index);
+    s.query("alpha beta gamma");
+    s.terms_matching_strategy(TermsMatchingStrategy::Frequency);
+    let SearchResult { documents_ids, .. } = s.execute().unwrap();
+
+    // all the words are as frequent, so they are removed from last to first
+    insta::assert_snapshot!(format!("{documents_ids:?}"), @"[0, 2]");
+}
+
 #[test]
 fn test_words_tms_all() {
     let index = create_index();
@@ -458,3 +589,379 @@ fn test_words_tms_all() {
     let texts = collect_field_values(&index, &txn, "text", &documents_ids);
     insta::assert_debug_snapshot!(texts, @"[]");
 }
+
+/// Sorts all the documents of the index with the `words` ranking rule alone.
+fn words_bucket_sort(
+    index: &TempIndex,
+    txn: &RoTxn<'_>,
+    query: &str,
+    relaxation_strategy: impl WordRelaxationStrategy + 'static,
+    max_relaxation_steps: Option<usize>,
+    removed_term_ids: BTreeSet<u8>,
+) -> BucketSortOutput {
+    let words = Words::new(Box::new(relaxation_strategy), max_relaxation_steps, removed_term_ids);
+    words_rule_bucket_sort(index, txn, query, words)
+}
+
+/// Sorts all the documents of the index with the given `words` ranking rule alone.
+fn words_rule_bucket_sort(
+    index: &TempIndex,
+    txn: &RoTxn<'_>,
+    query: &str,
+    words: Words,
+) -> BucketSortOutput {
+    let mut ctx = SearchContext::new(index, txn).unwrap();
+    let tokenizer = Tokenizer::default();
+    let ExtractedTokens { query_terms, .. } =
+        located_query_terms_from_tokens(&mut ctx, tokenizer.tokenize(query), None).unwrap();
+    let (query_graph, _) = QueryGraph::from_query(&mut ctx, &query_terms).unwrap();
+    let universe = index.documents_ids(txn).unwrap();
+
+    let mut ranking_rules: Vec<BoxRankingRule<QueryGraph>> = vec![Box::new(words)];
+    bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &query_graph,
+        &universe,
+        0,
+        100,
+        &mut DefaultSearchLogger,
+        BucketSortOptions {
+            scoring_strategy: ScoringStrategy::Detailed,
+            details: true,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_words_tms_all_single_bucket() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let mut s = Search::new(&txn, &index);
+    s.query("quick brown fox");
+    s.terms_matching_strategy(TermsMatchingStrategy::All);
+    s.limit(100);
+    let SearchResult { documents_ids: mut all_ids, .. } = s.execute().unwrap();
+    all_ids.sort_unstable();
+
+    let output = words_bucket_sort(
+        &index,
+        &txn,
+        "quick brown fox",
+        TermsMatchingStrategy::All,
+        None,
+        BTreeSet::new(),
+    );
+
+    // the documents matching all the words, with the same maximum score
+    assert_eq!(output.docids, all_ids);
+    let words =
+        ScoreDetails::Words(score_details::Words { matching_words: 1, max_matching_words: 1 });
+    assert!(output.scores.iter().all(|scores| scores[..] == [words]), "{:#?}", output.scores);
+    assert!(output.dropped_terms.iter().all(Vec::is_empty));
+}
+
+#[test]
+fn test_words_tms_dropped_terms() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let output = words_bucket_sort(
+        &index,
+        &txn,
+        "the quick brown fox jumps",
+        TermsMatchingStrategy::Last,
+        None,
+        BTreeSet::new(),
+    );
+
+    let dropped_terms = |docid: u32| {
+        let position = output.docids.iter().position(|&id| id == docid).unwrap();
+        (output.matched_words[position], output.dropped_terms[position].clone())
+    };
+    // "the quick brown fox jumps"
+    assert_eq!(dropped_terms(5), (5, vec![]));
+    // "the quick brown fox"
+    assert_eq!(dropped_terms(4), (4, vec![4]));
+    // "the quick brown"
+    assert_eq!(dropped_terms(3), (3, vec![3, 4]));
+    // "the"
+    assert_eq!(dropped_terms(1), (1, vec![1, 2, 3, 4]));
+}
+
+#[test]
+fn test_words_tms_max_relaxation_steps() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let query = "the quick brown fox jumps";
+    let uncapped =
+        words_bucket_sort(&index, &txn, query, TermsMatchingStrategy::Last, None, BTreeSet::new());
+    let capped = words_bucket_sort(
+        &index,
+        &txn,
+        query,
+        TermsMatchingStrategy::Last,
+        Some(2),
+        BTreeSet::new(),
+    );
+
+    // the same documents are returned, only the last bucket is sorted differently
+    let (mut capped_ids, mut uncapped_ids) = (capped.docids.clone(), uncapped.docids);
+    capped_ids.sort_unstable();
+    uncapped_ids.sort_unstable();
+    assert_eq!(capped_ids, uncapped_ids);
+
+    let score = |docid: u32| {
+        let position = capped.docids.iter().position(|&id| id == docid).unwrap();
+        capped.scores[position].clone()
+    };
+    let words = |matching_words, max_matching_words| {
+        vec![ScoreDetails::Words(score_details::Words { matching_words, max_matching_words })]
+    };
+    // "the quick brown fox jumps", "the quick brown fox" and "the quick brown"
+    assert_eq!(score(5), words(4, 4));
+    assert_eq!(score(4), words(3, 4));
+    assert_eq!(score(3), words(2, 4));
+    // "the quick" and "the" are collapsed into the last bucket
+    assert_eq!(score(2), words(1, 4));
+    assert_eq!(score(1), words(1, 4));
+}
+
+#[test]
+fn test_words_tms_removed_terms() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    // "fox" and "jumps" are removed before the first bucket
+    let output = words_bucket_sort(
+        &index,
+        &txn,
+        "the quick brown fox jumps",
+        TermsMatchingStrategy::Last,
+        None,
+        BTreeSet::from([3, 4]),
+    );
+
+    let first_bucket = |docid: u32| {
+        let position = output.docids.iter().position(|&id| id == docid).unwrap();
+        (output.scores[position].clone(), output.dropped_terms[position].clone())
+    };
+    let words = |matching_words, max_matching_words| {
+        vec![ScoreDetails::Words(score_details::Words { matching_words, max_matching_words })]
+    };
+    // the first bucket matches "the quick brown", only the two remaining removal steps are counted
+    assert_eq!(first_bucket(5), (words(3, 3), vec![3, 4]));
+    assert_eq!(first_bucket(4), (words(3, 3), vec![3, 4]));
+    assert_eq!(first_bucket(3), (words(3, 3), vec![3, 4]));
+    // "the quick"
+    assert_eq!(first_bucket(2), (words(2, 3), vec![2, 3, 4]));
+    // "the"
+    assert_eq!(first_bucket(1), (words(1, 3), vec![1, 2, 3, 4]));
+}
+
+#[test]
+fn test_words_tms_query_too_complex() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let tokenizer = Tokenizer::default();
+    let ExtractedTokens { query_terms, .. } = located_query_terms_from_tokens(
+        &mut ctx,
+        tokenizer.tokenize("the quick brown fox jumps"),
+        None,
+    )
+    .unwrap();
+    let (query_graph, _) = QueryGraph::from_query(&mut ctx, &query_terms).unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+
+    // the words and their ngrams make more than 10 nodes
+    ctx.max_query_graph_nodes = 10;
+    let mut ranking_rules: Vec<BoxRankingRule<QueryGraph>> =
+        vec![Box::new(Words::new(Box::new(TermsMatchingStrategy::Last), None, BTreeSet::new()))];
+    let result = bucket_sort(
+        &mut ctx,
+        &mut ranking_rules,
+        &query_graph,
+        &universe,
+        0,
+        100,
+        &mut DefaultSearchLogger,
+        BucketSortOptions { scoring_strategy: ScoringStrategy::Detailed, ..Default::default() },
+    );
+    let Err(error) = result else { panic!("the query graph should be too complex") };
+    assert!(
+        matches!(
+            error,
+            Error::UserError(UserError::QueryTooComplex { nodes, max_nodes: 10 }) if nodes > 10
+        ),
+        "unexpected error: {error}"
+    );
+}
+
+#[test]
+fn test_words_tms_count_only() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let mut ctx = SearchContext::new(&index, &txn).unwrap();
+    let tokenizer = Tokenizer::default();
+    let ExtractedTokens { query_terms, .. } =
+        located_query_terms_from_tokens(&mut ctx, tokenizer.tokenize("quick brown fox"), None)
+            .unwrap();
+    let (query_graph, _) = QueryGraph::from_query(&mut ctx, &query_terms).unwrap();
+    let universe = index.documents_ids(&txn).unwrap();
+
+    let mut search = |length: usize| {
+        let mut ranking_rules: Vec<BoxRankingRule<QueryGraph>> =
+            vec![Box::new(Words::new(Box::new(TermsMatchingStrategy::All), None, BTreeSet::new()))];
+        bucket_sort(
+            &mut ctx,
+            &mut ranking_rules,
+            &query_graph,
+            &universe,
+            0,
+            length,
+            &mut DefaultSearchLogger,
+            BucketSortOptions::default(),
+        )
+        .unwrap()
+    };
+
+    // the page contains all the documents matching the query
+    let page = search(100);
+    let matching: RoaringBitmap = page.docids.iter().copied().collect();
+    assert_ne!(matching, universe);
+
+    let count_only = search(0);
+    assert!(count_only.docids.is_empty());
+    assert_eq!(count_only.all_candidates, matching);
+    assert_eq!(count_only.estimated_total_hits, matching.len());
+}
+
+/// A relaxation strategy removing the query words in the reverse order of a terms matching strategy
+struct ReversedRelaxation(TermsMatchingStrategy);
+
+impl WordRelaxationStrategy for ReversedRelaxation {
+    fn removal_order(
+        &self,
+        ctx: &mut SearchContext<'_>,
+        query_graph: &QueryGraph,
+    ) -> Result<Vec<SmallBitmap<QueryNode>>> {
+        let mut removal_order = self.0.removal_order(ctx, query_graph)?;
+        removal_order.reverse();
+        Ok(removal_order)
+    }
+}
+
+#[test]
+fn test_words_custom_relaxation_strategy() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let output = words_bucket_sort(
+        &index,
+        &txn,
+        "the quick brown fox jumps",
+        ReversedRelaxation(TermsMatchingStrategy::Last),
+        None,
+        BTreeSet::new(),
+    );
+
+    let dropped_terms = |docid: u32| {
+        let position = output.docids.iter().position(|&id| id == docid).unwrap();
+        (output.matched_words[position], output.dropped_terms[position].clone())
+    };
+    // "the quick brown fox jumps"
+    assert_eq!(dropped_terms(5), (5, vec![]));
+    // "quick" is removed first and "jumps" last, so the documents without "jumps" only match "the"
+    let only_the = (1, vec![1, 2, 3, 4]);
+    assert_eq!(dropped_terms(4), only_the);
+    assert_eq!(dropped_terms(2), only_the);
+    assert_eq!(dropped_terms(1), only_the);
+}
+
+#[test]
+fn test_words_exact_boost() {
+    let index = create_index();
+
+    let txn = index.read_txn().unwrap();
+    let search = |exact_boost: Option<f64>| {
+        let words = Words::new(Box::new(TermsMatchingStrategy::Last), None, BTreeSet::new());
+        let words = match exact_boost {
+            Some(exact_boost) => words.with_exact_boost(exact_boost).unwrap(),
+            None => words,
+        };
+        let output = words_rule_bucket_sort(&index, &txn, "the quick brown", words);
+        let score = |docid: u32| {
+            let position = output.docids.iter().position(|&id| id == docid).unwrap();
+            ScoreDetails::global_score(output.scores[position].iter())
+        };
+        // "the quick brown" and "the quick"
+        (output.docids.clone(), score(3), score(2))
+    };
+
+    let (docids, exact, relaxed) = search(None);
+    assert_eq!((exact, relaxed), (1., 2. / 3.));
+
+    for exact_boost in [0.1, 0.5, 0.8, 0.99] {
+        let (boosted_docids, boosted_exact, boosted_relaxed) = search(Some(exact_boost));
+        // the order of the documents doesn't change
+        assert_eq!(boosted_docids, docids);
+        assert_eq!(boosted_exact, 1.);
+        assert!(boosted_exact - boosted_relaxed >= exact_boost, "{exact_boost}: {boosted_relaxed}");
+    }
+
+    // the scores are reported with the bonus given to the exact matches
+    let words = Words::new(Box::new(TermsMatchingStrategy::Last), None, BTreeSet::new());
+    let output = words_rule_bucket_sort(
+        &index,
+        &txn,
+        "the quick brown",
+        words.with_exact_boost(0.5).unwrap(),
+    );
+    assert_eq!(
+        output.scores[0],
+        [ScoreDetails::BoostedWords(score_details::BoostedWords {
+            words: score_details::Words { matching_words: 3, max_matching_words: 3 },
+            exact_bonus: 1,
+        })]
+    );
+
+    // the exact matches can't be boosted above the whole range of the scores
+    for exact_boost in [-0.1, 1., f64::NAN] {
+        let words = Words::new(Box::new(TermsMatchingStrategy::Last), None, BTreeSet::new());
+        let error = words.with_exact_boost(exact_boost).err().unwrap();
+        assert!(
+            matches!(error, Error::UserError(UserError::InvalidExactBoost { .. })),
+            "unexpected error: {error}"
+        );
+    }
+
+    // the exact boost is given to the search
+    let search = |exact_boost: f64| {
+        let mut s = Search::new(&txn, &index);
+        s.query("the quick brown");
+        s.terms_matching_strategy(TermsMatchingStrategy::Last);
+        s.scoring_strategy(ScoringStrategy::Detailed);
+        s.exact_boost(exact_boost);
+        s.execute()
+    };
+    let SearchResult { document_scores, .. } = search(0.5).unwrap();
+    assert_eq!(
+        document_scores[0][0],
+        ScoreDetails::BoostedWords(score_details::BoostedWords {
+            words: score_details::Words { matching_words: 3, max_matching_words: 3 },
+            exact_bonus: 1,
+        })
+    );
+    let error = search(1.).err().unwrap();
+    assert!(
+        matches!(error, Error::UserError(UserError::InvalidExactBoost { .. })),
+        "unexpected error: {error}"
+    );
+}
@@ -94,11 +94,11 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for VectorSort<Q> {
         let vector_candidates = &self.vector_candidates & universe;
 
         if vector_candidates.is_empty() {
-            return Ok(Some(RankingRuleOutput {
+            return Ok(Some(RankingRuleOutput::whole(
                 query,
-                candidates: universe.clone(),
-                score: ScoreDetails::Vector(score_details::Vector { similarity: None }),
-            }));
+                universe,
+                ScoreDetails::Vector(score_details::Vector { similarity: None }),
+            )));
         }
 
         for (docid, distance) in self.cached_sorted_docids.by_ref() {
@@ -108,11 +108,11 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for VectorSort<Q> {
                     .distribution_shift
                     .map(|distribution| distribution.shift(score))
                     .unwrap_or(score);
-                return Ok(Some(RankingRuleOutput {
+                return Ok(Some(RankingRuleOutput::single(
                     query,
-                    candidates: RoaringBitmap::from_iter([docid]),
-                    score: ScoreDetails::Vector(score_details::Vector { similarity: Some(score) }),
-                }));
+                    docid,
+                    ScoreDetails::Vector(score_details::Vector { similarity: Some(score) }),
+                )));
             }
         }
 
@@ -124,11 +124,11 @@ impl<'ctx, Q: RankingRuleQueryTrait> RankingRule<'ctx, Q> for VectorSort<Q> {
         // it means we don't actually have any document remaining in the universe with a vector.
         // => exit
         if self.cached_sorted_docids.len() == 0 {
-            return Ok(Some(RankingRuleOutput {
+            return Ok(Some(RankingRuleOutput::whole(
                 query,
-                candidates: universe.clone(),
-                score: ScoreDetails::Vector(score_details::Vector { similarity: None }),
-            }));
+                universe,
+                ScoreDetails::Vector(score_details::Vector { similarity: None }),
+            )));
         }
 
         self.next_bucket(ctx, _logger, universe)
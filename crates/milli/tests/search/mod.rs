@@ -193,10 +193,19 @@ pub fn expected_order(
 
     match optional_words {
         TermsMatchingStrategy::Last => groups.into_iter().flatten().collect(),
+        TermsMatchingStrategy::First => {
+            // the first query words are removed first, and all the documents of the test set
+            // that miss a query word also miss the last one, so they are never returned
+            groups.into_iter().flatten().filter(|d| d.word_rank == 0).collect()
+        }
         TermsMatchingStrategy::Frequency => groups.into_iter().flatten().collect(),
         TermsMatchingStrategy::All => {
             groups.into_iter().flatten().filter(|d| d.word_rank == 0).collect()
         }
+        TermsMatchingStrategy::MinMatch(min_match) => {
+            let max_word_rank = TEST_QUERY.split_whitespace().count().saturating_sub(min_match);
+            groups.into_iter().flatten().filter(|d| d.word_rank as usize <= max_word_rank).collect()
+        }
     }
 }
 
@@ -17,6 +17,7 @@ use crate::search::{self, EXTERNAL_DOCUMENTS_IDS};
 
 const ALLOW_OPTIONAL_WORDS: TermsMatchingStrategy = TermsMatchingStrategy::Last;
 const DISALLOW_OPTIONAL_WORDS: TermsMatchingStrategy = TermsMatchingStrategy::All;
+const ALLOW_OPTIONAL_FIRST_WORDS: TermsMatchingStrategy = TermsMatchingStrategy::First;
 const ASC_DESC_CANDIDATES_THRESHOLD: usize = 1000;
 
 macro_rules! test_criterion {
@@ -48,6 +49,7 @@ macro_rules! test_criterion {
 
 test_criterion!(none, DISALLOW_OPTIONAL_WORDS, vec![], vec![]);
 test_criterion!(words, ALLOW_OPTIONAL_WORDS, vec![Words], vec![]);
+test_criterion!(words_first, ALLOW_OPTIONAL_FIRST_WORDS, vec![Words], vec![]);
 test_criterion!(attribute, DISALLOW_OPTIONAL_WORDS, vec![Attribute], vec![]);
 test_criterion!(typo, DISALLOW_OPTIONAL_WORDS, vec![Typo], vec![]);
 test_criterion!(exactness, DISALLOW_OPTIONAL_WORDS, vec![Exactness], vec![]);
@@ -88,6 +90,28 @@ test_criterion!(
     vec![]
 );
 
+#[test]
+fn words_first_and_last_orders_differ() {
+    let index = search::setup_search_index_with_criteria(&[Words]);
+    let rtxn = index.read_txn().unwrap();
+
+    let execute = |optional_words| {
+        let mut search = Search::new(&rtxn, &index);
+        search.query(search::TEST_QUERY);
+        search.limit(EXTERNAL_DOCUMENTS_IDS.len());
+        search.terms_matching_strategy(optional_words);
+        let SearchResult { documents_ids, .. } = search.execute().unwrap();
+        search::internal_to_external_ids(&index, &documents_ids)
+    };
+
+    // `hello` then `world` are dropped first, and the documents missing a query word also miss `america`
+    let first = execute(ALLOW_OPTIONAL_FIRST_WORDS);
+    assert_eq!(first, ["A", "C", "D", "I", "K", "L", "M", "N", "O", "P"]);
+
+    let last = execute(ALLOW_OPTIONAL_WORDS);
+    assert_ne!(first, last);
+}
+
 #[test]
 fn criteria_mixup() {
     use Criterion::*;